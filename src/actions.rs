@@ -0,0 +1,211 @@
+use crate::scanning::device_id;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Deletes a file, or a directory with everything in it, symlinks are deleted without their target
+///
+/// Directories with other filesystems mounted within them are not deleted at all, like with
+/// `rm --one-file-system`. A directory that can't be deleted completely keeps the entries that
+/// couldn't be deleted.
+pub fn delete(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        check_one_filesystem(path)?;
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Fails if another filesystem is mounted within a directory, deleting or moving the directory
+/// with everything in it would also remove the contents of the mounted filesystem
+fn check_one_filesystem(dir: &Path) -> io::Result<()> {
+    match mount_point_within(dir, device_id(&fs::metadata(dir)?))? {
+        Some(mount_point) => Err(io::Error::new(
+            io::ErrorKind::CrossesDevices,
+            format!(
+                "another filesystem is mounted at {}, {} is left as it is",
+                mount_point.display(),
+                dir.display()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Finds a directory within dir that is on another device than the given one, symlinks are not
+/// followed
+fn mount_point_within(dir: &Path, device: Option<u64>) -> io::Result<Option<PathBuf>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if device_id(&fs::symlink_metadata(&path)?) != device {
+            return Ok(Some(path));
+        }
+        if let Some(mount_point) = mount_point_within(&path, device)? {
+            return Ok(Some(mount_point));
+        }
+    }
+    Ok(None)
+}
+
+/// Moves a file or directory into another directory, it's copied and deleted if the directory is
+/// on another filesystem
+///
+/// Returns the new path of the entry, an entry with the same name in the directory is not replaced.
+pub fn move_to(path: &Path, dir: &Path) -> io::Result<PathBuf> {
+    if !fs::metadata(dir)?.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be moved", path.display()),
+        )
+    })?;
+    let target = dir.join(name);
+    if fs::symlink_metadata(&target).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", target.display()),
+        ));
+    }
+    match fs::rename(path, &target) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            // the original is deleted after copying it, which has to stay on its filesystem
+            if fs::symlink_metadata(path)?.is_dir() {
+                check_one_filesystem(path)?;
+            }
+            if let Err(err) = copy(path, &target) {
+                // not leaving a partial copy behind
+                let _ = delete(&target);
+                return Err(err);
+            }
+            delete(path)?;
+        }
+        result => result?,
+    }
+    Ok(target)
+}
+
+/// Copies a file or a directory with everything in it, symlinks are copied as links
+fn copy(path: &Path, target: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        fs::create_dir(target)?;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            copy(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        fs::set_permissions(target, metadata.permissions())
+    } else if metadata.file_type().is_symlink() {
+        copy_link(path, target)
+    } else {
+        fs::copy(path, target).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_link(path: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(path)?, target)
+}
+
+#[cfg(not(unix))]
+fn copy_link(path: &Path, _target: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("the link {} can't be copied", path.display()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::actions::{copy, delete, device_id, mount_point_within, move_to};
+    use std::fs;
+
+    #[test]
+    fn test_delete() {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir_all(data.join("nested")).unwrap();
+        fs::write(data.join("nested/file"), "data").unwrap();
+        let file = root.path().join("file");
+        fs::write(&file, "data").unwrap();
+
+        delete(&file).unwrap();
+        assert!(!file.exists());
+        #[cfg(unix)]
+        {
+            // only the link is deleted, not the directory it points to
+            let link = root.path().join("link");
+            std::os::unix::fs::symlink(&data, &link).unwrap();
+            delete(&link).unwrap();
+            assert!(data.join("nested/file").exists());
+        }
+        delete(&data).unwrap();
+        assert!(!data.exists());
+        assert!(delete(&data).is_err());
+    }
+
+    #[test]
+    fn test_mount_points_not_deleted() {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir_all(data.join("nested/deeper")).unwrap();
+        fs::write(data.join("nested/file"), "data").unwrap();
+        let device = device_id(&fs::metadata(&data).unwrap());
+
+        assert_eq!(mount_point_within(&data, device).unwrap(), None);
+        // directories on any other device are mount points
+        let other_device = Some(device.map_or(1, |device| device + 1));
+        assert_eq!(
+            mount_point_within(&data, other_device).unwrap(),
+            Some(data.join("nested"))
+        );
+        #[cfg(target_os = "linux")]
+        {
+            // links to other filesystems are not followed, /proc is always a filesystem of its own
+            std::os::unix::fs::symlink("/proc", data.join("nested/proc")).unwrap();
+            assert_eq!(mount_point_within(&data, device).unwrap(), None);
+        }
+        delete(&data).unwrap();
+        assert!(!data.exists());
+    }
+
+    #[test]
+    fn test_move_to() {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir_all(data.join("nested")).unwrap();
+        fs::write(data.join("nested/file"), "nested").unwrap();
+        let target = root.path().join("target");
+        fs::create_dir(&target).unwrap();
+
+        let moved = move_to(&data, &target).unwrap();
+        assert_eq!(moved, target.join("data"));
+        assert!(!data.exists());
+        assert_eq!(
+            fs::read_to_string(moved.join("nested/file")).unwrap(),
+            "nested"
+        );
+        // existing entries are not replaced
+        fs::create_dir(&data).unwrap();
+        assert!(move_to(&data, &target).is_err());
+        assert!(data.exists());
+
+        // copied when moving to another filesystem
+        let copied = root.path().join("copied");
+        copy(&moved, &copied).unwrap();
+        assert_eq!(
+            fs::read_to_string(copied.join("nested/file")).unwrap(),
+            "nested"
+        );
+        assert!(moved.join("nested/file").exists());
+    }
+}
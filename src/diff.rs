@@ -0,0 +1,267 @@
+use crate::structs::{Dir, SizeFormat, SizeMode, Usage};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// How an entry of a diff tree differs from the same path in the older tree
+///
+/// contains:
+/// - old - the usage of the entry in the older tree, None if it was added
+/// - removed - the entry only exists in the older tree, its sizes in the diff tree are 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub old: Option<Usage>,
+    pub removed: bool,
+}
+
+/// How the size of an entry changed between two trees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Grown,
+    Shrunk,
+    Unchanged,
+}
+
+impl ChangeKind {
+    pub fn label(&self) -> &str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Grown => "grown",
+            ChangeKind::Shrunk => "shrunk",
+            ChangeKind::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Compares two scans of the same directory
+///
+/// The diff tree has the entries and sizes of the new tree, every entry records its usage in the
+/// old tree. Entries that were removed are kept with a size of 0, under the paths of the new
+/// tree. The contents of directories that were collapsed in either tree are not compared.
+pub fn diff_trees(old: &Dir, new: &Dir) -> Dir {
+    let mut diff = without_contents(new);
+    diff.change = Some(Change {
+        old: Some(usage(old)),
+        removed: false,
+    });
+    diff.contents = match (&old.contents, &new.contents) {
+        (Some(old_contents), Some(new_contents)) => {
+            let mut old_by_name: HashMap<&OsStr, &Dir> = old_contents
+                .iter()
+                .filter_map(|old| Some((old.path.file_name()?, old)))
+                .collect();
+            let mut contents: Vec<Dir> = new_contents
+                .iter()
+                .map(|new| {
+                    let old = new
+                        .path
+                        .file_name()
+                        .and_then(|name| old_by_name.remove(name));
+                    match old {
+                        Some(old) => diff_trees(old, new),
+                        None => added(new),
+                    }
+                })
+                .collect();
+            let mut removed_entries: Vec<&Dir> = old_by_name.into_values().collect();
+            removed_entries.sort_by(|a, b| a.path.cmp(&b.path));
+            for old in removed_entries {
+                let name = old.path.file_name().unwrap_or_default();
+                contents.push(removed(old, &new.path.join(name)));
+            }
+            Some(contents)
+        }
+        _ => new.contents.clone(),
+    };
+    diff
+}
+
+/// The entries of a diff tree that changed in the given size mode, from the biggest to the
+/// smallest change
+pub fn changed_entries(diff: &Dir, mode: SizeMode) -> Vec<&Dir> {
+    fn collect<'d>(dir: &'d Dir, mode: SizeMode, changed: &mut Vec<&'d Dir>) {
+        let kind = match dir.change_kind(mode) {
+            Some(kind) => kind,
+            None => return,
+        };
+        if kind != ChangeKind::Unchanged {
+            changed.push(dir);
+        }
+        // unchanged directories can still have changes within them that cancel out
+        for sub_dir in dir.contents.iter().flatten() {
+            collect(sub_dir, mode, changed);
+        }
+    }
+    let mut changed = vec![];
+    collect(diff, mode, &mut changed);
+    changed.sort_by_key(|dir| Reverse(dir.delta(mode).unwrap_or(0).unsigned_abs()));
+    changed
+}
+
+impl Dir {
+    /// Change of the size in the given mode since the older tree, None if this is not a diff tree
+    pub fn delta(&self, mode: SizeMode) -> Option<i64> {
+        let change = self.change?;
+        let old = change.old.map_or(0, |old| old.size_in(mode));
+        Some(self.size_in(mode) as i64 - old as i64)
+    }
+
+    /// How the entry changed since the older tree, None if this is not a diff tree
+    pub fn change_kind(&self, mode: SizeMode) -> Option<ChangeKind> {
+        let change = self.change?;
+        let kind = if change.removed {
+            ChangeKind::Removed
+        } else if change.old.is_none() {
+            ChangeKind::Added
+        } else {
+            match self.delta(mode)? {
+                delta if delta > 0 => ChangeKind::Grown,
+                delta if delta < 0 => ChangeKind::Shrunk,
+                _ => ChangeKind::Unchanged,
+            }
+        };
+        Some(kind)
+    }
+}
+
+/// String representation of a size change, e.g. `+12.50 mb`
+pub fn format_delta(size_fmt: &SizeFormat, delta: i64) -> String {
+    let (formated_size, format_str) = size_fmt.format(delta.unsigned_abs());
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{:.2} {}", sign, formated_size, format_str)
+}
+
+fn usage(dir: &Dir) -> Usage {
+    Usage {
+        size: dir.size,
+        disk_size: dir.disk_size,
+        file_count: dir.file_count,
+    }
+}
+
+/// A copy of the entry without its contents
+fn without_contents(dir: &Dir) -> Dir {
+    Dir {
+        path: dir.path.clone(),
+        contents: None,
+        hidden: vec![],
+        target: dir.target.clone(),
+        error: dir.error.clone(),
+        change: None,
+        ..*dir
+    }
+}
+
+/// An entry that only exists in the new tree
+fn added(new: &Dir) -> Dir {
+    let mut diff = without_contents(new);
+    diff.change = Some(Change {
+        old: None,
+        removed: false,
+    });
+    diff.contents = new
+        .contents
+        .as_ref()
+        .map(|contents| contents.iter().map(added).collect());
+    diff
+}
+
+/// An entry that only exists in the old tree, moved to its path in the new tree
+fn removed(old: &Dir, path: &Path) -> Dir {
+    let mut diff = without_contents(old);
+    diff.path = PathBuf::from(path);
+    diff.size = 0;
+    diff.disk_size = 0;
+    diff.own_size = 0;
+    diff.own_disk_size = 0;
+    diff.file_count = 0;
+    diff.dir_count = 0;
+    diff.error_count = 0;
+    diff.ignored_size = 0;
+    diff.ignored_disk_size = 0;
+    diff.change = Some(Change {
+        old: Some(usage(old)),
+        removed: true,
+    });
+    diff.contents = old.contents.as_ref().map(|contents| {
+        contents
+            .iter()
+            .map(|old| removed(old, &path.join(old.path.file_name().unwrap_or_default())))
+            .collect()
+    });
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use crate::diff::{changed_entries, diff_trees, ChangeKind};
+    use crate::structs::{make_file as file, Dir, SizeMode};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_diff_trees() {
+        let old = Dir::from_contents(
+            PathBuf::from("/old/data"),
+            vec![
+                Dir::from_contents(
+                    PathBuf::from("/old/data/logs"),
+                    vec![file("/old/data/logs/a.log", 100)],
+                ),
+                Dir::from_contents(
+                    PathBuf::from("/old/data/cache"),
+                    vec![file("/old/data/cache/blob", 500)],
+                ),
+                file("/old/data/same", 10),
+                file("/old/data/smaller", 70),
+            ],
+        );
+        let new = Dir::from_contents(
+            PathBuf::from("/data"),
+            vec![
+                Dir::from_contents(
+                    PathBuf::from("/data/logs"),
+                    vec![
+                        file("/data/logs/a.log", 300),
+                        file("/data/logs/b.log", 1000),
+                    ],
+                ),
+                file("/data/same", 10),
+                file("/data/smaller", 20),
+            ],
+        );
+        let diff = diff_trees(&old, &new);
+        let mode = SizeMode::Apparent;
+        assert_eq!(diff.size, new.size);
+        assert_eq!(diff.delta(mode), Some(new.size as i64 - old.size as i64));
+
+        let kind = |path: &str| diff.get(Path::new(path)).unwrap().change_kind(mode);
+        assert_eq!(kind("/data/logs"), Some(ChangeKind::Grown));
+        assert_eq!(kind("/data/logs/b.log"), Some(ChangeKind::Added));
+        assert_eq!(kind("/data/cache"), Some(ChangeKind::Removed));
+        assert_eq!(kind("/data/cache/blob"), Some(ChangeKind::Removed));
+        assert_eq!(kind("/data/same"), Some(ChangeKind::Unchanged));
+        assert_eq!(kind("/data/smaller"), Some(ChangeKind::Shrunk));
+        assert_eq!(diff.get(Path::new("/data/cache")).unwrap().size, 0);
+
+        let changed: Vec<(&Path, i64)> = changed_entries(&diff, mode)
+            .into_iter()
+            .map(|dir| (dir.path.as_path(), dir.delta(mode).unwrap()))
+            .collect();
+        assert_eq!(
+            changed,
+            vec![
+                (Path::new("/data/logs"), 1200),
+                (Path::new("/data/logs/b.log"), 1000),
+                (Path::new("/data"), 650),
+                (Path::new("/data/cache"), -500),
+                (Path::new("/data/cache/blob"), -500),
+                (Path::new("/data/logs/a.log"), 200),
+                (Path::new("/data/smaller"), -50),
+            ]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use log::warn;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Glob patterns that decide which entries are skipped while scanning
+///
+/// Patterns are matched against both the name and the full path of an entry, so `*.iso` and
+/// `node_modules` match anywhere in the tree. Include patterns only apply to files, directories are
+/// always descended into unless they are excluded.
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    exclude: GlobSet,
+    include: Option<GlobSet>,
+    /// the patterns the sets were built from
+    patterns: (Vec<String>, Vec<String>),
+}
+
+impl PathFilter {
+    /// Create a new filter from exclude and include glob patterns
+    ///
+    /// Args:
+    /// - exclude - entries matching any of these patterns are skipped
+    /// - include - if not empty, only files matching one of these patterns are scanned
+    pub fn new(exclude: &[String], include: &[String]) -> Result<Self, globset::Error> {
+        let patterns = (exclude.to_vec(), include.to_vec());
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(build_set(include)?)
+        };
+        Ok(Self {
+            exclude: build_set(exclude)?,
+            include,
+            patterns,
+        })
+    }
+
+    /// Describes the patterns of the filter, filters with the same patterns skip the same entries
+    pub fn fingerprint(&self) -> String {
+        let (exclude, include) = &self.patterns;
+        format!("exclude={:?} include={:?}", exclude, include)
+    }
+
+    /// Reads glob patterns from a file, one per line, skipping empty lines and `#` comments
+    pub fn read_patterns(path: &Path) -> io::Result<Vec<String>> {
+        let patterns = fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect();
+        Ok(patterns)
+    }
+
+    /// Checks if an entry should be skipped
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let matches = |set: &GlobSet| {
+            set.is_match(path) || path.file_name().is_some_and(|name| set.is_match(name))
+        };
+        if matches(&self.exclude) {
+            return true;
+        }
+        match &self.include {
+            Some(include) if !is_dir => !matches(include),
+            _ => false,
+        }
+    }
+}
+
+fn build_set(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    builder.build()
+}
+
+/// Parses an age like `90s`, `30m`, `12h`, `180d`, `6w` or `2y` (365 days) into a duration
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (number, unit) = age.split_at(split);
+    let number: u64 = number.parse().map_err(|_| {
+        format!(
+            "Invalid age: {}\nexpected a number followed by a unit, e.g. 180d",
+            age
+        )
+    })?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Unrecognized age unit: {}\nexpected one of: [s, m, h, d, w, y]",
+                unit
+            ))
+        }
+    };
+    Ok(Duration::from_secs(number.saturating_mul(unit_secs)))
+}
+
+/// Ignore files that are read in every directory, later ones take precedence
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// The `.gitignore`/`.ignore` matchers of a directory and all of its parents
+///
+/// The bottom of the stack holds the global git excludes, matchers of deeper directories take
+/// precedence over the ones of their parents, the same way git resolves them.
+pub(crate) struct IgnoreStack<'p> {
+    matcher: Gitignore,
+    parent: Option<&'p IgnoreStack<'p>>,
+}
+
+impl<'p> IgnoreStack<'p> {
+    /// Create the bottom of the stack from the global git excludes (`core.excludesFile`)
+    pub(crate) fn global() -> Self {
+        let (matcher, err) = Gitignore::global();
+        if let Some(err) = err {
+            warn!(
+                "Error occured when reading the global git excludes: {}",
+                err
+            );
+        }
+        Self {
+            matcher,
+            parent: None,
+        }
+    }
+
+    /// Pushes the ignore files of a directory on top of the stack
+    ///
+    /// Returns None if the directory has no ignore files (`names` are the entry names of the directory)
+    pub(crate) fn push<'s>(
+        &'s self,
+        dir: &Path,
+        names: &[&std::ffi::OsStr],
+    ) -> Option<IgnoreStack<'s>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for ignore_file in IGNORE_FILES {
+            if names.iter().any(|name| *name == ignore_file) {
+                if let Some(err) = builder.add(dir.join(ignore_file)) {
+                    warn!("Error occured when reading an ignore file: {}", err);
+                }
+                found = true;
+            }
+        }
+        if !found {
+            return None;
+        }
+        let matcher = builder.build().unwrap_or_else(|err| {
+            warn!("Error occured when building ignore rules: {}", err);
+            Gitignore::empty()
+        });
+        Some(IgnoreStack {
+            matcher,
+            parent: Some(self),
+        })
+    }
+
+    /// Pushes the ignore files of every directory of `dirs` in order, then calls `f` with the
+    /// resulting stack, used to start scanning below the root with the rules of its parents
+    pub(crate) fn push_dirs<R>(&self, dirs: &[&Path], f: impl FnOnce(&IgnoreStack) -> R) -> R {
+        let (dir, dirs) = match dirs.split_first() {
+            Some(split) => split,
+            None => return f(self),
+        };
+        let names: Vec<&std::ffi::OsStr> = IGNORE_FILES
+            .iter()
+            .map(std::ffi::OsStr::new)
+            .filter(|name| dir.join(name).is_file())
+            .collect();
+        match self.push(dir, &names) {
+            Some(pushed) => pushed.push_dirs(dirs, f),
+            None => self.push_dirs(dirs, f),
+        }
+    }
+
+    /// Checks if an entry is ignored, the `.git` directory itself is never tracked
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let mut stack = Some(self);
+        while let Some(level) = stack {
+            match level.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => stack = level.parent,
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::filter::{parse_age, PathFilter};
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_is_excluded() {
+        let filter = PathFilter::new(
+            &["*.iso".to_string(), "node_modules".to_string()],
+            &["*.rs".to_string(), "*.iso".to_string()],
+        )
+        .unwrap();
+
+        assert!(filter.is_excluded(Path::new("./images/disk.iso"), false));
+        assert!(filter.is_excluded(Path::new("./web/node_modules"), true));
+        assert!(filter.is_excluded(Path::new("./readme.md"), false));
+        assert!(!filter.is_excluded(Path::new("./src/lib.rs"), false));
+        assert!(!filter.is_excluded(Path::new("./src"), true));
+        assert!(!PathFilter::default().is_excluded(Path::new("./readme.md"), false));
+    }
+
+    #[test]
+    fn test_parse_age() {
+        let day = 24 * 60 * 60;
+        assert_eq!(parse_age("180d"), Ok(Duration::from_secs(180 * day)));
+        assert_eq!(parse_age("6w"), Ok(Duration::from_secs(42 * day)));
+        assert_eq!(parse_age("2y"), Ok(Duration::from_secs(730 * day)));
+        assert_eq!(parse_age("90s"), Ok(Duration::from_secs(90)));
+        assert!(parse_age("180").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3 months").is_err());
+    }
+}
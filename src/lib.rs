@@ -1,3 +1,11 @@
+pub mod actions;
+pub mod diff;
+pub mod filter;
 pub mod menu;
+pub mod owners;
 pub mod scanning;
+pub mod snapshot;
 pub mod structs;
+pub mod trash;
+pub mod types;
+pub mod watch;
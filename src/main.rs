@@ -1,39 +1,577 @@
 extern crate dirsize;
-use clap::Parser;
-use crossterm::Result;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
+use crossterm::{cursor, queue, style, terminal, Result};
+use dirsize::diff::{changed_entries, diff_trees, format_delta, ChangeKind};
+use dirsize::filter::{parse_age, PathFilter};
 use dirsize::menu::Menu;
-use dirsize::scanning::make_dir_tree_parallel;
-use dirsize::structs::SizeFormat;
-use std::path::PathBuf;
+use dirsize::owners::{OwnerKind, OwnerNames};
+use dirsize::scanning::{
+    make_dir_tree_live, make_dir_tree_parallel_with, CancelToken, ProgressCallback, ScanCache,
+    ScanOptions, SymlinkPolicy,
+};
+use dirsize::snapshot::{Snapshot, SnapshotFormat};
+use dirsize::structs::{
+    Dir, EntryKind, IgnoreView, SharedDir, SizeFormat, SizeMode, SortBy, Usage,
+};
+use dirsize::types::extension_label;
+use dirsize::watch::{watch_tree, WatchState};
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    override_usage = "dirsize [OPTIONS] <PATH>\n       dirsize [OPTIONS] <COMMAND>"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// path to dirrectory
-    #[arg()]
-    path: PathBuf,
+    #[arg(required = true)]
+    path: Option<PathBuf>,
     /// size format, possible values : [gb, mb, kb, b]
-    #[arg(short, long, default_value = "mb")]
+    #[arg(short, long, default_value = "mb", global = true)]
     size: Option<SizeFormat>,
+    /// show the apparent size of files (their length in bytes)
+    #[arg(long, conflicts_with = "disk_usage", global = true)]
+    apparent_size: bool,
+    /// show the space allocated for files on disk, like du does [default]
+    #[arg(long, global = true)]
+    disk_usage: bool,
+    /// how symlinks are treated, possible values : [never, follow]
+    #[arg(long, default_value = "never", global = true)]
+    symlinks: Option<SymlinkPolicy>,
+    /// stay on the filesystem of PATH, mount points are not scanned
+    #[arg(short = 'x', long, global = true)]
+    one_file_system: bool,
+    /// skip entries matching the glob pattern, can be repeated
+    #[arg(long, value_name = "GLOB", global = true)]
+    exclude: Vec<String>,
+    /// read exclude glob patterns from a file, one per line
+    #[arg(long, value_name = "FILE", global = true)]
+    exclude_from: Option<PathBuf>,
+    /// only scan files matching the glob pattern, can be repeated
+    #[arg(long, value_name = "GLOB", global = true)]
+    include: Vec<String>,
+    /// sum up the size of excluded entries on their parent directory
+    #[arg(long, global = true)]
+    summarize_excluded: bool,
+    /// detect entries ignored by .gitignore/.ignore files and only show the not ignored ones
+    #[arg(long, conflicts_with = "only_ignored", global = true)]
+    gitignore: bool,
+    /// detect entries ignored by .gitignore/.ignore files and only show the ignored ones
+    #[arg(long, global = true)]
+    only_ignored: bool,
+    /// don't keep directories deeper than N below PATH, their sizes are summed up into their parents
+    #[arg(short = 'd', long, value_name = "N", global = true)]
+    max_depth: Option<usize>,
+    /// scan the whole directory before opening the menu instead of filling it in while scanning
+    #[arg(long, global = true)]
+    no_live: bool,
+    /// only scan files last modified longer than AGE ago, e.g. 180d, units : [s, m, h, d, w, y]
+    #[arg(long, value_name = "AGE", value_parser = parse_age, global = true)]
+    older_than: Option<Duration>,
+    /// stop scanning after SECS seconds and show the directories scanned so far
+    #[arg(long, value_name = "SECS", global = true)]
+    timeout: Option<u64>,
+    /// reuse the directories that didn't change since the snapshot FILE was saved, the scan is saved to it again
+    #[arg(long, value_name = "FILE", global = true)]
+    cache: Option<PathBuf>,
+    /// keep watching PATH after the scan and update the sizes in the menu as files change
+    #[arg(long, conflicts_with_all = ["by_owner", "by_type"])]
+    watch: bool,
+    /// print the size of PATH by the user and group owning the files instead of opening the menu
+    #[arg(long, global = true)]
+    by_owner: bool,
+    /// print the size of PATH by file category and extension instead of opening the menu
+    #[arg(long, global = true)]
+    by_type: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// scan PATH and save the tree to a snapshot file instead of opening the menu
+    Scan {
+        /// path to dirrectory
+        path: PathBuf,
+        /// file the snapshot is saved to, as JSON if it ends with .json and in a compact binary format otherwise
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+    },
+    /// open the menu on a saved snapshot without scanning the filesystem
+    Open {
+        /// snapshot file saved with the scan command
+        snapshot: PathBuf,
+    },
+    /// compare two snapshots and print what was added, removed, grown or shrunk
+    Diff {
+        /// the older snapshot, or a directory that is scanned
+        old: PathBuf,
+        /// the newer snapshot, or a directory that is scanned
+        new: PathBuf,
+        /// open the menu on the differences instead of printing them
+        #[arg(long)]
+        menu: bool,
+        /// print only the N biggest changes
+        #[arg(short = 'n', long, value_name = "N", default_value = "50")]
+        top: usize,
+    },
+}
+
+/// Parses the arguments, the options can be given before or after the subcommand
+fn parse_args<I, T>(args: I) -> std::result::Result<Args, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args = Args::try_parse_from(args)?;
+    if args.command.is_some() && args.path.is_some() {
+        return Err(Args::command().error(
+            ErrorKind::ArgumentConflict,
+            "PATH can't be used with a subcommand, the subcommands take their own paths",
+        ));
+    }
+    Ok(args)
 }
 
 fn main() -> Result<()> {
     // Parsing arguments
-    let args = Args::parse();
-    let root_path = args.path;
+    let args = parse_args(env::args_os()).unwrap_or_else(|err| err.exit());
     let size_format = args.size.unwrap();
+    let size_mode = if args.apparent_size {
+        SizeMode::Apparent
+    } else {
+        SizeMode::Disk
+    };
+
+    let mut exclude = args.exclude;
+    if let Some(exclude_from) = args.exclude_from {
+        exclude.extend(PathFilter::read_patterns(&exclude_from)?);
+    }
+    let filter = PathFilter::new(&exclude, &args.include)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    // Ctrl-C and the timeout stop the scan, the menu is still opened on the partial tree
+    let cancel = CancelToken::new();
+    if let Some(timeout) = args.timeout {
+        cancel.cancel_after(Duration::from_secs(timeout));
+    }
+    let scans = match &args.command {
+        Some(Command::Open { .. }) => false,
+        Some(Command::Diff { old, new, .. }) => old.is_dir() || new.is_dir(),
+        _ => true,
+    };
+    if scans {
+        stop_scans_on_ctrl_c(&cancel)?;
+    }
+    let options = ScanOptions {
+        symlinks: args.symlinks.unwrap(),
+        one_file_system: args.one_file_system,
+        filter,
+        summarize_excluded: args.summarize_excluded,
+        gitignore: args.gitignore || args.only_ignored,
+        max_depth: args.max_depth,
+        progress: None,
+        cancel: Some(cancel.clone()),
+        modified_before: args.older_than.map(|age| {
+            SystemTime::now()
+                .checked_sub(age)
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        }),
+        cache: args.cache.as_deref().and_then(load_cache),
+    };
+    let ignore_view = if args.only_ignored {
+        IgnoreView::Ignored
+    } else if args.gitignore {
+        IgnoreView::Tracked
+    } else {
+        IgnoreView::All
+    };
+
+    // a report on a partial tree would leave out everything that wasn't scanned
+    let print_reports = |dir: &Dir| {
+        if dir.incomplete {
+            exit_incomplete(&dir.path);
+        }
+        if args.by_owner {
+            print_owners(dir, &size_format, size_mode);
+        }
+        if args.by_type {
+            print_types(dir, &size_format, size_mode);
+        }
+    };
+    let root_path = match args.command {
+        Some(Command::Scan { path, output }) => {
+            let dir = scan_with_progress(path, &options, &size_format)?;
+            if dir.incomplete {
+                println!("Scan stopped, the snapshot is incomplete");
+            }
+            if let Some(cache) = &args.cache {
+                save_cache(&dir, &options, cache)?;
+            }
+            Snapshot::new(dir, options.fingerprint())
+                .save(&output, SnapshotFormat::from_path(&output))?;
+            println!("Saved snapshot to {}", output.display());
+            return Ok(());
+        }
+        Some(Command::Open { snapshot }) => {
+            let snapshot = Snapshot::load(&snapshot)?;
+            if args.by_owner || args.by_type {
+                print_reports(&snapshot.root);
+                return Ok(());
+            }
+            let menu = Menu::new(Arc::new(Mutex::new(snapshot.root)), size_format)
+                .size_mode(size_mode)
+                .ignore_view(ignore_view)
+                .snapshot(snapshot.created);
+            return run_menu(menu);
+        }
+        Some(Command::Diff {
+            old,
+            new,
+            menu,
+            top,
+        }) => {
+            let old_tree = load_tree(&old, &options, &size_format)?;
+            let new_tree = load_tree(&new, &options, &size_format)?;
+            let diff = diff_trees(&old_tree.root, &new_tree.root);
+            if !menu {
+                print_diff(&diff, top, &size_format, size_mode);
+                return Ok(());
+            }
+            let menu = Menu::new(Arc::new(Mutex::new(diff)), size_format)
+                .size_mode(size_mode)
+                .ignore_view(ignore_view)
+                .sort_by(SortBy::Change)
+                .snapshot(new_tree.created);
+            return run_menu(menu);
+        }
+        None => args.path.unwrap(),
+    };
 
     // Scaning the directory structure
+    let watch = args.watch.then(WatchState::new);
+    let mut live_scan = None;
+    let dir = if args.no_live || args.by_owner || args.by_type {
+        let dir = scan_with_progress(root_path, &options, &size_format)?;
+        if let Some(cache) = &args.cache {
+            save_cache(&dir, &options, cache)?;
+        }
+        if args.by_owner || args.by_type {
+            print_reports(&dir);
+            return Ok(());
+        }
+        let dir = Arc::new(Mutex::new(dir));
+        if let Some(watch) = &watch {
+            start_watching(dir.clone(), &options, watch.clone());
+        }
+        dir
+    } else {
+        // the menu is started right away and filled in by the scan running in the background
+        let mut root = Dir::new(0, root_path, None, EntryKind::Directory);
+        root.pending = true;
+        let dir = Arc::new(Mutex::new(root));
+        let (tree, scan_options) = (dir.clone(), options.clone());
+        let (cache, watch) = (args.cache.clone(), watch.clone());
+        live_scan = Some(thread::spawn(move || {
+            SCANNING.store(true, Ordering::SeqCst);
+            make_dir_tree_live(tree.clone(), &scan_options);
+            SCANNING.store(false, Ordering::SeqCst);
+            if let Some(watch) = watch {
+                start_watching(tree.clone(), &scan_options, watch);
+            }
+            match cache {
+                Some(cache) => save_cache(&tree.lock().unwrap(), &scan_options, &cache),
+                None => Ok(()),
+            }
+        }));
+        dir
+    };
+
+    // Starting menu
+    let mut menu = Menu::new(dir, size_format)
+        .size_mode(size_mode)
+        .ignore_view(ignore_view)
+        .scan_options(ScanOptions {
+            cancel: None,
+            cache: None,
+            ..options
+        })
+        .scan_cancel(cancel);
+    if let Some(watch) = watch {
+        menu = menu.watch(watch);
+    }
+    run_menu(menu)?;
+
+    // the cache is only saved if the scan was done before the menu was closed
+    match live_scan {
+        Some(live_scan) if live_scan.is_finished() => live_scan.join().unwrap(),
+        _ => Ok(()),
+    }
+}
+
+/// Runs the menu and prints the paths chosen in it once it's closed
+fn run_menu(mut menu: Menu) -> Result<()> {
+    menu.run()?;
+    for path in menu.printed_paths() {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Watches a scanned tree for changes on a background thread
+fn start_watching(tree: SharedDir, options: &ScanOptions, watch: WatchState) {
+    let options = options.clone();
+    thread::spawn(move || watch_tree(tree, &options, watch));
+}
+
+/// Loads the tree of an earlier scan to reuse its unchanged directories, a missing or unreadable
+/// cache is scanned from scratch
+fn load_cache(path: &Path) -> Option<ScanCache> {
+    if !path.exists() {
+        return None;
+    }
+    match Snapshot::load(path) {
+        Ok(snapshot) => Some(ScanCache::new(snapshot.root, snapshot.options)),
+        Err(err) => {
+            eprintln!("Not using the cache: {}", err);
+            None
+        }
+    }
+}
+
+/// Saves a scanned tree to be reused as the cache of the next scan with the same options
+fn save_cache(dir: &Dir, options: &ScanOptions, path: &Path) -> Result<()> {
+    let format = SnapshotFormat::from_path(path);
+    Snapshot::save_tree(dir, &options.fingerprint(), path, format)
+}
+
+/// Set while a scan is running, Ctrl-C only stops the scan then
+static SCANNING: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C stop the running scan, the tree scanned so far is still used, outside of a scan or
+/// once the scan was stopped Ctrl-C exits like it does without the handler
+fn stop_scans_on_ctrl_c(cancel: &CancelToken) -> io::Result<()> {
+    let cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if !SCANNING.load(Ordering::SeqCst) || cancel.is_cancelled() {
+            process::exit(130);
+        }
+        cancel.cancel();
+    })
+    .map_err(io::Error::other)
+}
+
+/// Scans a directory while printing the progress, sorted from the biggest to the smallest entry
+fn scan_with_progress(
+    root_path: PathBuf,
+    options: &ScanOptions,
+    size_format: &SizeFormat,
+) -> Result<Dir> {
     println!(
         "Running size calculation for directory: {}",
         root_path.display()
     );
-    let mut dir = make_dir_tree_parallel(root_path);
+    let scan_options = ScanOptions {
+        progress: Some(progress_printer(size_format.clone())),
+        ..options.clone()
+    };
+    SCANNING.store(true, Ordering::SeqCst);
+    let mut dir = make_dir_tree_parallel_with(root_path, &scan_options);
+    SCANNING.store(false, Ordering::SeqCst);
+    clear_progress()?;
 
     // Sorting the directory from bigest to smallest
     dir.sort_by_size();
+    Ok(dir)
+}
 
-    // Starting menu
-    let mut menu = Menu::new(&mut dir, size_format);
-    menu.run()
+/// Loads a tree from a snapshot file, or scans it if the path is a directory
+///
+/// Exits if the scan was stopped or the snapshot was saved from a stopped scan, comparing a
+/// partial tree would report everything that wasn't scanned as removed
+fn load_tree(path: &Path, options: &ScanOptions, size_format: &SizeFormat) -> Result<Snapshot> {
+    let snapshot = if path.is_dir() {
+        let dir = scan_with_progress(path.to_path_buf(), options, size_format)?;
+        Snapshot::new(dir, options.fingerprint())
+    } else {
+        Snapshot::load(path)?
+    };
+    if snapshot.root.incomplete {
+        exit_incomplete(path);
+    }
+    Ok(snapshot)
+}
+
+/// Exits with an error when a scan was stopped by Ctrl-C or the timeout before it was done,
+/// the diff and the reports are not made from partial trees
+fn exit_incomplete(path: &Path) -> ! {
+    eprintln!(
+        "Scan incomplete: {} wasn't fully scanned before it was stopped",
+        path.display()
+    );
+    process::exit(1)
+}
+
+/// Prints the biggest changes of a diff tree, from the biggest to the smallest
+fn print_diff(diff: &Dir, top: usize, size_format: &SizeFormat, size_mode: SizeMode) {
+    let changed = changed_entries(diff, size_mode);
+    println!(
+        "{} changed entries ({}), showing the {} biggest changes:",
+        changed.len(),
+        size_mode.label(),
+        top.min(changed.len())
+    );
+    for dir in changed.into_iter().take(top) {
+        let kind = dir.change_kind(size_mode).unwrap_or(ChangeKind::Unchanged);
+        println!(
+            "  {:>12} {:<7} {}",
+            format_delta(size_format, dir.delta(size_mode).unwrap_or(0)),
+            kind.label(),
+            dir.path.display()
+        );
+    }
+}
+
+/// Prints the usage of the tree by the users and groups owning the files
+fn print_owners(dir: &Dir, size_format: &SizeFormat, size_mode: SizeMode) {
+    let names = OwnerNames::load();
+    for kind in [OwnerKind::User, OwnerKind::Group] {
+        let usage = dir
+            .usage_by_owner(kind, size_mode)
+            .into_iter()
+            .map(|(id, usage)| (names.name(kind, id), usage))
+            .collect();
+        let heading = format!("by {} ({}):", kind.label(), size_mode.label());
+        print_usage(&heading, usage, size_format, size_mode);
+    }
+}
+
+/// Prints the usage of the tree by file category and extension
+fn print_types(dir: &Dir, size_format: &SizeFormat, size_mode: SizeMode) {
+    let categories = dir
+        .usage_by_category(size_mode)
+        .into_iter()
+        .map(|(category, usage)| (category.label().to_string(), usage))
+        .collect();
+    let heading = format!("by category ({}):", size_mode.label());
+    print_usage(&heading, categories, size_format, size_mode);
+    let extensions = dir
+        .usage_by_extension(size_mode)
+        .into_iter()
+        .map(|(extension, usage)| (extension_label(extension), usage))
+        .collect();
+    let heading = format!("by extension ({}):", size_mode.label());
+    print_usage(&heading, extensions, size_format, size_mode);
+}
+
+/// Prints a heading followed by the aligned usage of every group
+fn print_usage(
+    heading: &str,
+    usage: Vec<(String, Usage)>,
+    size_format: &SizeFormat,
+    size_mode: SizeMode,
+) {
+    println!("{}", heading);
+    let max_len = usage.iter().map(|(name, _)| name.chars().count()).max();
+    for (name, usage) in usage {
+        println!(
+            "  {:<max_len$} - {}",
+            name,
+            usage.display(size_format, size_mode),
+            max_len = max_len.unwrap_or(0)
+        );
+    }
+}
+
+/// Makes a progress callback that prints the progress of the scan on a single line
+fn progress_printer(size_format: SizeFormat) -> ProgressCallback {
+    const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+    let last_print = Mutex::new((Instant::now(), 0));
+    ProgressCallback::new(move |progress| {
+        // printing at most every 100ms, skipping if another thread is already printing
+        let mut last_print = match last_print.try_lock() {
+            Ok(last_print) => last_print,
+            Err(_) => return,
+        };
+        if last_print.0.elapsed() < Duration::from_millis(100) {
+            return;
+        }
+        *last_print = (Instant::now(), last_print.1 + 1);
+
+        let (formated_size, format_str) = size_format.format(progress.bytes);
+        let line = format!(
+            "{} {} files, {} dirs, {:.2} {}, {} errors - {}",
+            SPINNER[last_print.1 % SPINNER.len()],
+            progress.files,
+            progress.dirs,
+            formated_size,
+            format_str,
+            progress.errors,
+            progress.current_path.display()
+        );
+        let (terminal_width, _) = terminal::size().unwrap_or((80, 0));
+        let line: String = line.chars().take(terminal_width as usize - 1).collect();
+        let mut stderr = io::stderr();
+        let _ = queue!(
+            stderr,
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            style::Print(line)
+        );
+        let _ = stderr.flush();
+    })
+}
+
+/// Clears the progress line after the scan is done
+fn clear_progress() -> Result<()> {
+    let mut stderr = io::stderr();
+    queue!(
+        stderr,
+        cursor::MoveToColumn(0),
+        terminal::Clear(terminal::ClearType::CurrentLine)
+    )?;
+    stderr.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{parse_args, Command};
+    use std::path::Path;
+
+    #[test]
+    fn test_options_around_subcommands() {
+        for args in [
+            ["dirsize", "--apparent-size", "scan", "src", "-o", "x"],
+            ["dirsize", "scan", "src", "-o", "x", "--apparent-size"],
+        ] {
+            let args = parse_args(args).unwrap();
+            assert!(args.apparent_size);
+            match args.command {
+                Some(Command::Scan { path, output }) => {
+                    assert_eq!(path, Path::new("src"));
+                    assert_eq!(output, Path::new("x"));
+                }
+                command => panic!("parsed {:?} instead of the scan command", command),
+            }
+        }
+
+        let args = parse_args(["dirsize", "-d", "2", "src"]).unwrap();
+        assert_eq!(args.max_depth, Some(2));
+        assert_eq!(args.path.as_deref(), Some(Path::new("src")));
+        assert!(parse_args(["dirsize"]).is_err());
+        assert!(parse_args(["dirsize", "other", "scan", "src", "-o", "x"]).is_err());
+    }
 }
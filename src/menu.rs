@@ -1,26 +1,106 @@
-use crate::structs::{Dir, SizeFormat};
+use crate::actions::{delete, move_to};
+use crate::diff::{format_delta, ChangeKind};
+use crate::owners::{OwnerKind, OwnerNames};
+use crate::scanning::{make_dir_tree_within, scan_entry, CancelToken, ScanOptions, ScanReport};
+use crate::structs::{Dir, EntryKind, IgnoreView, SharedDir, SizeFormat, SizeMode, SortBy, Usage};
+use crate::trash::{trash, TrashedEntry};
+use crate::types::extension_label;
+use crate::watch::WatchState;
 use crossterm::{
     cursor,
-    event::{Event, KeyCode, KeyEventKind},
+    event::{Event, KeyCode, KeyEventKind, KeyModifiers},
     queue, style, terminal, Result,
 };
 use opener::open;
-use std::io::Write;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 impl Dir {
-    fn display_menu(&self, size_fmt: &SizeFormat, max_len: Option<usize>) -> String {
-        let (formated_size, format_str) = self.size_formated(size_fmt);
+    fn display_menu(&self, size_fmt: &SizeFormat, view: View, max_len: Option<usize>) -> String {
+        let (formated_size, format_str) = size_fmt.format(view.size(self));
         let max_len = max_len.unwrap_or(25);
+        let note = if self.error.is_some() {
+            " (unreadable)"
+        } else if self.hard_link && self.is_dir() {
+            " (already counted through a symlink)"
+        } else if self.hard_link {
+            " (hard link, already counted)"
+        } else if self.kind == EntryKind::MountPoint {
+            " (mount point, not scanned)"
+        } else if self.collapsed {
+            " [+]"
+        } else if self.pending {
+            " [scanning]"
+        } else if self.incomplete {
+            " (incomplete)"
+        } else {
+            ""
+        };
+        // the count column is left empty for files
+        let count = if self.is_dir() {
+            format!(" - {:>9} items", self.entry_count())
+        } else {
+            String::new()
+        };
+        let modified = match self.newest_modified {
+            Some(modified) => format_age(modified),
+            None => String::new(),
+        };
+        // diff trees show the change since the older tree next to the size
+        let (delta, change) = match (self.delta(view.size_mode), self.change_kind(view.size_mode)) {
+            (Some(delta), Some(kind)) => {
+                let change = match kind {
+                    ChangeKind::Added | ChangeKind::Removed => format!(" ({})", kind.label()),
+                    _ => String::new(),
+                };
+                (format!(" ({:>12})", format_delta(size_fmt, delta)), change)
+            }
+            _ => (String::new(), String::new()),
+        };
         format!(
-            "{:<max_len$} - {:.2} {}",
-            self.name(),
+            "{:<max_len$} - {:>8.2} {}{} - {:>8}{}{}{}",
+            self.menu_name(),
             formated_size,
             format_str,
+            delta,
+            modified,
+            count,
+            note,
+            change,
             max_len = max_len
         )
     }
-    fn color(&self) -> style::Color {
-        if self.is_file {
+    /// Name of the directory/file, symlinks also show where they point to
+    fn menu_name(&self) -> String {
+        match &self.target {
+            Some(target) => format!("{} -> {}", self.name(), target.display()),
+            None => self.name().to_string(),
+        }
+    }
+    fn color(&self, mode: SizeMode) -> style::Color {
+        if self.error.is_some() {
+            return style::Color::DarkRed;
+        }
+        // in diff trees growth is red and shrinkage green
+        match self.change_kind(mode) {
+            Some(ChangeKind::Added | ChangeKind::Grown) => return style::Color::Red,
+            Some(ChangeKind::Removed | ChangeKind::Shrunk) => return style::Color::Green,
+            Some(ChangeKind::Unchanged) => return style::Color::DarkGrey,
+            None => {}
+        }
+        if self.hard_link {
+            style::Color::DarkGrey
+        } else if self.kind == EntryKind::Symlink {
+            style::Color::Cyan
+        } else if self.kind == EntryKind::MountPoint {
+            style::Color::Magenta
+        } else if self.ignored {
+            style::Color::DarkYellow
+        } else if self.is_file() {
             style::Color::Red
         } else {
             style::Color::White
@@ -30,36 +110,359 @@ impl Dir {
 
 const SIZE_FILTER_MIN: u64 = 1000000;
 
-pub struct Menu<'a> {
-    root_dir: &'a Dir,
-    selected_dir: &'a Dir,
-    filtered: Vec<&'a Dir>,
+/// How often the menu is redrawn while the tree is still being scanned
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Every key of the menu and what it does, listed by [?], the footer only shows the main ones
+const KEYS: [(&str, &str); 20] = [
+    ("↑ & ↓", "move"),
+    ("→ or [Enter]", "open the selected dir"),
+    ("← or [Backspace]", "go back to the parent dir"),
+    ("[Esc]", "exit program"),
+    ("[Ctrl-C]", "stop the scan, exit program once it is stopped"),
+    ("[o]", "open the selected dir in the file manager"),
+    ("[r]", "rescan the listed dir"),
+    ("[d]", "delete the selected entry"),
+    ("[x]", "move the selected entry to the trash"),
+    ("[u]", "undo the last move to the trash"),
+    ("[space]", "mark the selected entry"),
+    ("[b]", "batch actions on the marked entries"),
+    ("[a]", "switch between apparent and disk size"),
+    ("[g]", "switch the gitignore view"),
+    ("[s]", "sort by size, count, modified or change"),
+    ("[e]", "read errors"),
+    ("[w]", "owners of the selected dir"),
+    ("[t]", "file types in the selected dir"),
+    ("[+]", "collapsed dir, scanned when opened"),
+    ("[?]", "this list of keys"),
+];
+
+/// Which sizes of the directories/files are shown and how they are sorted
+#[derive(Debug, Clone, Copy)]
+struct View {
+    size_mode: SizeMode,
+    ignore_view: IgnoreView,
+    sort_by: SortBy,
+}
+
+impl View {
+    fn size(&self, dir: &Dir) -> u64 {
+        dir.size_in_view(self.size_mode, self.ignore_view)
+    }
+
+    /// The value the listed contents are sorted by, from biggest to smallest
+    fn sort_key(&self, dir: &Dir) -> u64 {
+        match self.sort_by {
+            SortBy::Size => self.size(dir),
+            SortBy::Count => dir.entry_count(),
+            SortBy::Modified => dir.newest_modified.map_or(0, |modified| {
+                SystemTime::now()
+                    .duration_since(modified)
+                    .map_or(0, |age| age.as_secs())
+            }),
+            SortBy::Change => dir
+                .delta(self.size_mode)
+                .map_or(0, |delta| delta.unsigned_abs()),
+        }
+    }
+
+    fn label(&self) -> String {
+        let mut label = self.size_mode.label().to_string();
+        if self.ignore_view != IgnoreView::All {
+            label = format!("{}, {}", label, self.ignore_view.label());
+        }
+        if self.sort_by != SortBy::Size {
+            label = format!("{}, {}", label, self.sort_by.label());
+        }
+        label
+    }
+}
+
+/// A list shown over the directory contents (read errors, owners, ...)
+///
+/// contains:
+/// - title - the first line of the list
+/// - lines - the text and color of every line
+/// - key - the key the list was opened with, it also closes it
+struct Overlay {
+    title: String,
+    lines: Vec<(String, style::Color)>,
+    cursor_pos: usize,
+    key: char,
+}
+
+impl Overlay {
+    fn new(title: String, lines: Vec<(String, style::Color)>, key: char) -> Self {
+        Self {
+            title,
+            lines,
+            cursor_pos: 0,
+            key,
+        }
+    }
+
+    /// Handles a key press, returns false if the list is closed
+    fn key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Esc | KeyCode::Backspace | KeyCode::Left => {
+                return false;
+            }
+            KeyCode::Char(key) if key == self.key => {
+                return false;
+            }
+            KeyCode::Up => {
+                if self.cursor_pos > 0 {
+                    self.cursor_pos -= 1;
+                } else {
+                    self.cursor_pos = self.lines.len().saturating_sub(1);
+                }
+            }
+            KeyCode::Down => {
+                if self.cursor_pos + 1 < self.lines.len() {
+                    self.cursor_pos += 1;
+                } else {
+                    self.cursor_pos = 0;
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+pub struct Menu {
+    root_dir: SharedDir,
+    selected_path: PathBuf,
     cursor_pos: usize,
     size_fmt: SizeFormat,
+    view: View,
     last_selected: Vec<usize>,
+    scan_options: ScanOptions,
+    scan_cancel: Option<CancelToken>,
+    snapshot: Option<SystemTime>,
+    watch: Option<WatchState>,
+    overlay: Option<Overlay>,
+    /// the entries moved to the trash by the last trash action, to undo it
+    last_trashed: Vec<TrashedEntry>,
+    /// entries marked for a batch action
+    marked: BTreeSet<PathBuf>,
+    /// paths printed once the menu is closed
+    printed: Vec<PathBuf>,
 }
 
-impl<'a> Menu<'a> {
-    pub fn new(dir: &'a mut Dir, size_fmt: SizeFormat) -> Self {
+impl Menu {
+    /// Create a new menu for a directory tree
+    ///
+    /// The tree can still be built up by a scan running on another thread, the menu is redrawn
+    /// while the root directory is pending.
+    pub fn new(dir: SharedDir, size_fmt: SizeFormat) -> Self {
         let cursor_pos = 0;
-        let filtered = dir
-            .filter_size(SIZE_FILTER_MIN)
-            .expect("Expected dir to have files/dirs above a minimum size of 1mb"); // filters dirs for size above a threshold
+        let view = View {
+            size_mode: SizeMode::default(),
+            ignore_view: IgnoreView::default(),
+            sort_by: SortBy::default(),
+        };
+        let selected_path = dir.lock().unwrap().path.clone();
         let last_selected = vec![]; // used to track the directory tree traversal
         Self {
             root_dir: dir,
-            selected_dir: dir,
-            filtered,
+            selected_path,
             cursor_pos,
             size_fmt,
+            view,
             last_selected,
+            scan_options: ScanOptions::default(),
+            scan_cancel: None,
+            snapshot: None,
+            watch: None,
+            overlay: None,
+            last_trashed: vec![],
+            marked: BTreeSet::new(),
+            printed: vec![],
         }
     }
 
-    fn draw_directory_path(&self, stdout: &mut impl Write) -> Result<()> {
+    /// Sets the size mode the menu starts with
+    pub fn size_mode(mut self, size_mode: SizeMode) -> Self {
+        self.view.size_mode = size_mode;
+        self
+    }
+
+    /// Sets the ignore view the menu starts with
+    pub fn ignore_view(mut self, ignore_view: IgnoreView) -> Self {
+        self.view.ignore_view = ignore_view;
+        self
+    }
+
+    /// Sets the order the contents are listed in when the menu starts
+    pub fn sort_by(mut self, sort_by: SortBy) -> Self {
+        self.view.sort_by = sort_by;
+        self
+    }
+
+    /// Sets the options used for scanning collapsed directories when they are entered
+    pub fn scan_options(mut self, scan_options: ScanOptions) -> Self {
+        self.scan_options = scan_options;
+        self
+    }
+
+    /// Sets the token of the scan building the tree, it is cancelled with Ctrl-C
+    pub fn scan_cancel(mut self, scan_cancel: CancelToken) -> Self {
+        self.scan_cancel = Some(scan_cancel);
+        self
+    }
+
+    /// Marks the tree as loaded from a snapshot created at the given time, the filesystem is
+    /// not scanned again
+    pub fn snapshot(mut self, created: SystemTime) -> Self {
+        self.snapshot = Some(created);
+        self
+    }
+
+    /// Sets the state of the watcher updating the tree, the menu is redrawn whenever it changes
+    pub fn watch(mut self, watch: WatchState) -> Self {
+        self.watch = Some(watch);
+        self
+    }
+
+    /// Paths chosen to be printed when the menu was closed, for piping them to other tools
+    pub fn printed_paths(&self) -> &[PathBuf] {
+        &self.printed
+    }
+
+    /// Number of times the tree was updated by the watcher
+    fn watch_updates(&self) -> u64 {
+        self.watch.as_ref().map_or(0, WatchState::updates)
+    }
+
+    /// The directory whose contents are listed
+    fn selected_dir<'d>(&self, root_dir: &'d Dir) -> &'d Dir {
+        root_dir.get(&self.selected_path).unwrap_or(root_dir)
+    }
+
+    /// The listed contents of the selected directory
+    fn filtered<'d>(&self, root_dir: &'d Dir) -> Vec<&'d Dir> {
+        filter_sorted(self.selected_dir(root_dir), self.view).unwrap_or_default()
+    }
+
+    /// Number of the listed contents of the selected directory
+    fn filtered_len(&self) -> usize {
+        self.filtered(&self.root_dir.lock().unwrap()).len()
+    }
+
+    /// Checks if the tree is still being scanned
+    fn is_scanning(&self) -> bool {
+        self.root_dir.lock().unwrap().pending
+    }
+
+    fn draw_directory_path(&self, stdout: &mut impl Write, root_dir: &Dir) -> Result<()> {
+        let selected_dir = self.selected_dir(root_dir);
         queue!(stdout, cursor::MoveTo(0, 0))?;
         queue!(stdout, style::SetForegroundColor(style::Color::Grey))?;
-        queue!(stdout, style::Print(self.selected_dir.path.display()))?;
+        queue!(
+            stdout,
+            style::Print(format!(
+                "{} ({})",
+                selected_dir.path.display(),
+                self.view.label()
+            ))
+        )?;
+        queue!(
+            stdout,
+            style::Print(format!(
+                " - {} files, {} dirs",
+                selected_dir.file_count, selected_dir.dir_count
+            ))
+        )?;
+        let excluded_count = selected_dir.excluded_count;
+        if excluded_count > 0 {
+            let excluded_size = match self.view.size_mode {
+                SizeMode::Apparent => selected_dir.excluded_size,
+                SizeMode::Disk => selected_dir.excluded_disk_size,
+            };
+            let summary = if excluded_size > 0 {
+                let (formated_size, format_str) = self.size_fmt.format(excluded_size);
+                format!(
+                    " [{} excluded, {:.2} {}]",
+                    excluded_count, formated_size, format_str
+                )
+            } else {
+                format!(" [{} excluded]", excluded_count)
+            };
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkGrey))?;
+            queue!(stdout, style::Print(summary))?;
+        }
+        if selected_dir.error_count > 0 {
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkRed))?;
+            queue!(
+                stdout,
+                style::Print(format!(" [{} unreadable]", selected_dir.error_count))
+            )?;
+        }
+        if root_dir.pending {
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkGrey))?;
+            queue!(stdout, style::Print(" scanning... (Ctrl-C to stop)"))?;
+        } else if root_dir.incomplete {
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkYellow))?;
+            queue!(
+                stdout,
+                style::Print(" [scan stopped, sizes are incomplete]")
+            )?;
+        }
+        if let Some(delta) = selected_dir.delta(self.view.size_mode) {
+            let color = match delta {
+                delta if delta > 0 => style::Color::Red,
+                delta if delta < 0 => style::Color::Green,
+                _ => style::Color::DarkGrey,
+            };
+            queue!(stdout, style::SetForegroundColor(color))?;
+            queue!(
+                stdout,
+                style::Print(format!(
+                    " [{} since the older scan]",
+                    format_delta(&self.size_fmt, delta)
+                ))
+            )?;
+        }
+        if let Some(created) = self.snapshot {
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkGrey))?;
+            queue!(
+                stdout,
+                style::Print(format!(" [snapshot from {}]", format_age(created)))
+            )?;
+        }
+        if let Some(watch) = &self.watch {
+            let (status, color) = match watch.error() {
+                Some(error) => (format!(" [{}]", error), style::Color::DarkRed),
+                None => (
+                    " [watching for changes]".to_string(),
+                    style::Color::DarkGrey,
+                ),
+            };
+            queue!(stdout, style::SetForegroundColor(color))?;
+            queue!(stdout, style::Print(status))?;
+        }
+        Ok(())
+    }
+
+    /// Draws the number and size of the marked entries above the navigation info
+    fn draw_marked_status(
+        &self,
+        stdout: &mut impl Write,
+        root_dir: &Dir,
+        marked: &[&Dir],
+    ) -> Result<()> {
+        let (_, terminal_height) = terminal::size().unwrap();
+        queue!(stdout, cursor::MoveTo(0, terminal_height.saturating_sub(2)))?;
+        queue!(stdout, style::SetForegroundColor(style::Color::Yellow))?;
+        queue!(
+            stdout,
+            style::Print(format!(
+                "{} marked ({}) - [b] batch actions",
+                marked.len(),
+                self.removal_summary(root_dir, marked)
+            ))
+        )?;
         Ok(())
     }
 
@@ -69,7 +472,7 @@ impl<'a> Menu<'a> {
         queue!(stdout, cursor::MoveToColumn(0))?;
         queue!(
             stdout,
-            style::Print("move with (↑ & ↓), navigate dirs (→ or [Enter] & ← or [Backspace]), [Esc] to exit program, [o] open dir")
+            style::Print("move with (↑ & ↓), navigate dirs (→ & ←), [Esc] to exit program, [d] delete, [space] mark, [?] all keys")
         )?;
         Ok(())
     }
@@ -90,97 +493,245 @@ impl<'a> Menu<'a> {
         Ok(())
     }
 
-    /// Draws the menu to the scren
-    fn draw(&self, stdout: &mut impl Write) -> Result<()> {
+    /// Draws a list over the directory contents
+    fn draw_overlay(&self, stdout: &mut impl Write, overlay: &Overlay) -> Result<()> {
         queue!(stdout, cursor::MoveTo(0, 0))?;
         queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
-        self.draw_directory_path(stdout)?;
+        queue!(stdout, style::SetForegroundColor(style::Color::Grey))?;
+        queue!(stdout, style::Print(&overlay.title))?;
         queue!(stdout, cursor::MoveDown(1))?;
         queue!(stdout, cursor::MoveToColumn(0))?;
-        for (i, item) in self.filtered.iter().enumerate() {
-            let (start_index, end_index) = self.calculate_index_bounds();
+        let (start_index, end_index) =
+            calculate_index_bounds(overlay.cursor_pos, overlay.lines.len(), 0);
+        for (i, (line, color)) in overlay.lines.iter().enumerate() {
             if (i >= start_index) & (i <= end_index) {
-                // Printing the cursor
-                if i == self.cursor_pos {
+                if i == overlay.cursor_pos {
                     queue!(stdout, style::SetForegroundColor(style::Color::White))?;
                     queue!(stdout, style::Print("> "))?;
                 } else {
                     queue!(stdout, style::Print("  "))?;
                 }
+                queue!(stdout, style::SetForegroundColor(*color))?;
+                queue!(stdout, style::Print(line))?;
+                queue!(stdout, cursor::MoveDown(1))?;
+                queue!(stdout, cursor::MoveToColumn(0))?;
+            }
+        }
+        queue!(stdout, style::SetForegroundColor(style::Color::White))?;
+        let (_, terminal_height) = terminal::size().unwrap();
+        queue!(stdout, cursor::MoveToRow(terminal_height))?;
+        queue!(stdout, cursor::MoveToColumn(0))?;
+        queue!(
+            stdout,
+            style::Print(format!(
+                "move with (↑ & ↓), [Esc] or [{}] back to the directories",
+                overlay.key
+            ))
+        )?;
+        stdout.flush()?;
+        Ok(())
+    }
+
+    /// Draws the menu to the scren
+    fn draw(&self, stdout: &mut impl Write) -> Result<()> {
+        if let Some(overlay) = &self.overlay {
+            return self.draw_overlay(stdout, overlay);
+        }
+        let root_dir = self.root_dir.lock().unwrap();
+        queue!(stdout, cursor::MoveTo(0, 0))?;
+        queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        self.draw_directory_path(stdout, &root_dir)?;
+        queue!(stdout, cursor::MoveDown(1))?;
+        queue!(stdout, cursor::MoveToColumn(0))?;
+        let filtered = self.filtered(&root_dir);
+        if filtered.is_empty() {
+            queue!(stdout, style::SetForegroundColor(style::Color::DarkGrey))?;
+            if self.selected_dir(&root_dir).pending {
+                queue!(stdout, style::Print("  (scanning...)"))?;
+            } else {
+                queue!(stdout, style::Print("  (no files/dirs above 1mb)"))?;
+            }
+        }
+        let marked = self.marked_entries(&root_dir);
+        // the status bar of the marked entries takes a line
+        let (start_index, end_index) =
+            calculate_index_bounds(self.cursor_pos, filtered.len(), !marked.is_empty() as usize);
+        let max_str_len = calculate_max_len(&filtered);
+        for (i, item) in filtered.iter().enumerate() {
+            if (i >= start_index) & (i <= end_index) {
+                // Printing the cursor
+                let cursor = if i == self.cursor_pos { '>' } else { ' ' };
+                let mark = if self.marked.contains(&item.path) {
+                    '*'
+                } else {
+                    ' '
+                };
+                queue!(stdout, style::SetForegroundColor(style::Color::White))?;
+                queue!(stdout, style::Print(format!("{}{}", cursor, mark)))?;
                 // Printing the items (dirrectories)
-                queue!(stdout, style::SetForegroundColor(item.color()))?;
-                let max_str_len = self.calculate_max_len();
                 queue!(
                     stdout,
-                    style::Print(item.display_menu(&self.size_fmt, Some(max_str_len)))
+                    style::SetForegroundColor(item.color(self.view.size_mode))
+                )?;
+                queue!(
+                    stdout,
+                    style::Print(item.display_menu(&self.size_fmt, self.view, Some(max_str_len)))
                 )?;
                 queue!(stdout, cursor::MoveDown(1))?;
                 queue!(stdout, cursor::MoveToColumn(0))?;
             }
         }
+        if !marked.is_empty() {
+            self.draw_marked_status(stdout, &root_dir, &marked)?;
+        }
         queue!(stdout, style::SetForegroundColor(style::Color::White))?;
         self.draw_navigation_info(stdout)?;
         stdout.flush()?;
         Ok(())
     }
 
-    /// Calculates the maximum directory name length of Vec<&Dir>
-    fn calculate_max_len(&self) -> usize {
-        self.filtered
-            .iter()
-            .map(|dir| dir.name().len())
-            .fold(0, |acc, l| if l > acc { l } else { acc })
-    }
-
     /// Start the menu
     pub fn run(&mut self) -> Result<()> {
-        // menu setup
-        let mut stdout = std::io::stdout();
-        terminal::enable_raw_mode().unwrap();
+        // menu setup, drawing on stderr when the output is piped so printed paths stay clean
+        let mut stdout: Box<dyn Write> = if std::io::stdout().is_terminal() {
+            Box::new(std::io::stdout())
+        } else {
+            Box::new(std::io::stderr())
+        };
+        terminal::enable_raw_mode()?;
         queue!(stdout, terminal::EnterAlternateScreen)?;
         queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
         queue!(stdout, cursor::Hide)?;
         stdout.flush()?;
 
         // menu input handling loop
+        let mut redraw = true;
+        let mut scanning = true;
+        let mut updates = self.watch_updates();
         loop {
-            self.draw(&mut stdout)?;
+            if redraw {
+                // the listed contents can change, keeping the cursor in range
+                self.cursor_pos = self.cursor_pos.min(self.filtered_len().saturating_sub(1));
+                self.draw(&mut stdout)?;
+            }
 
+            // redrawing while the tree is scanned, and once more after the scan is done
+            let was_scanning = scanning;
+            scanning = self.is_scanning();
+            if !crossterm::event::poll(REFRESH_INTERVAL)? {
+                // and whenever the watcher changed the tree
+                let last_updates = updates;
+                updates = self.watch_updates();
+                redraw = scanning || was_scanning || updates != last_updates;
+                continue;
+            }
+            redraw = true;
             if let Ok(Event::Key(key_event)) = crossterm::event::read() {
                 if let KeyEventKind::Press = key_event.kind {
+                    if let Some(overlay) = &mut self.overlay {
+                        if !overlay.key(key_event.code) {
+                            self.overlay = None;
+                        }
+                        continue;
+                    }
                     match key_event.code {
                         KeyCode::Esc => {
                             break;
                         }
+                        KeyCode::Char('c') if key_event.modifiers == KeyModifiers::CONTROL => {
+                            // stopping the scan first, the partial tree can still be browsed
+                            match &self.scan_cancel {
+                                Some(scan_cancel) if scanning && !scan_cancel.is_cancelled() => {
+                                    scan_cancel.cancel();
+                                }
+                                _ => break,
+                            }
+                        }
                         KeyCode::Up => {
                             if self.cursor_pos > 0 {
                                 self.cursor_pos -= 1;
                             } else {
-                                self.cursor_pos = self.filtered.len() - 1
+                                self.cursor_pos = self.filtered_len().saturating_sub(1);
                             }
                         }
                         KeyCode::Down => {
-                            if self.cursor_pos < self.filtered.len() - 1 {
+                            if self.cursor_pos + 1 < self.filtered_len() {
                                 self.cursor_pos += 1;
                             } else {
                                 self.cursor_pos = 0;
                             }
                         }
                         KeyCode::Enter | KeyCode::Right => {
-                            self.select_item();
+                            self.select_item(&mut stdout)?;
                         }
                         KeyCode::Backspace | KeyCode::Left => {
                             self.go_back();
                         }
-                        KeyCode::Char('o') => {
-                            if open(self.selected_dir.path.as_os_str()).is_err() {
-                                self.draw_warning(
-                                    &mut stdout,
-                                    "Failed to open the directory",
-                                    style::Color::Red,
-                                )?;
-                                block_until_key_press();
+                        KeyCode::Char('o') if open(self.selected_path.as_os_str()).is_err() => {
+                            self.draw_warning(
+                                &mut stdout,
+                                "Failed to open the directory",
+                                style::Color::Red,
+                            )?;
+                            block_until_key_press();
+                        }
+                        KeyCode::Char('d') => {
+                            self.delete_item(&mut stdout, scanning)?;
+                        }
+                        KeyCode::Char(' ') => {
+                            self.toggle_mark();
+                        }
+                        // closing the menu once the marked paths are chosen to be printed
+                        KeyCode::Char('b') if self.batch_action(&mut stdout, scanning)? => {
+                            break;
+                        }
+                        KeyCode::Char('x') => {
+                            self.trash_item(&mut stdout, scanning)?;
+                        }
+                        KeyCode::Char('u') => {
+                            self.undo_trash(&mut stdout, scanning)?;
+                        }
+                        KeyCode::Char('r') => {
+                            self.rescan_selected(&mut stdout, scanning)?;
+                        }
+                        KeyCode::Char('a') => {
+                            let view = View {
+                                size_mode: self.view.size_mode.toggle(),
+                                ..self.view
+                            };
+                            self.change_view(&mut stdout, view)?;
+                        }
+                        KeyCode::Char('e') => {
+                            self.show_errors(&mut stdout)?;
+                        }
+                        KeyCode::Char('w') => {
+                            self.show_owners();
+                        }
+                        KeyCode::Char('t') => {
+                            self.show_types();
+                        }
+                        KeyCode::Char('?') => {
+                            self.show_keys();
+                        }
+                        KeyCode::Char('g') => {
+                            let view = View {
+                                ignore_view: self.view.ignore_view.next(),
+                                ..self.view
+                            };
+                            self.change_view(&mut stdout, view)?;
+                        }
+                        KeyCode::Char('s') => {
+                            let mut sort_by = self.view.sort_by.next();
+                            // only diff trees can be sorted by the change
+                            let is_diff = self.root_dir.lock().unwrap().change.is_some();
+                            if sort_by == SortBy::Change && !is_diff {
+                                sort_by = sort_by.next();
                             }
+                            let view = View {
+                                sort_by,
+                                ..self.view
+                            };
+                            self.change_view(&mut stdout, view)?;
                         }
                         _ => {}
                     }
@@ -195,52 +746,687 @@ impl<'a> Menu<'a> {
         Ok(())
     }
 
-    /// Calculates the index range to print menu elements
-    fn calculate_index_bounds(&self) -> (usize, usize) {
-        let items_len = self.filtered.len() - 1;
-        let (terminal_width, terminal_height) = terminal::size().unwrap();
-        let (_, terminal_height) = (terminal_width as usize - 1, terminal_height as usize - 3);
-        if self.cursor_pos <= (terminal_height / 2) {
-            (0, terminal_height)
-        } else if items_len > self.cursor_pos + (terminal_height / 2) {
-            (
-                self.cursor_pos - (terminal_height / 2) - 1,
-                self.cursor_pos + (terminal_height / 2),
-            )
-        } else if items_len > terminal_height {
-            (
-                self.cursor_pos - (terminal_height - (items_len - self.cursor_pos)),
-                items_len,
-            )
-        } else {
-            (0, items_len)
-        }
-    }
-
-    /// Select a menu item
-    fn select_item(&mut self) {
-        let select = self
-            .filtered
+    /// Select a menu item, collapsed directories are scanned when they are entered
+    fn select_item(&mut self, stdout: &mut impl Write) -> Result<()> {
+        let (path, collapsed) = match self
+            .filtered(&self.root_dir.lock().unwrap())
             .get(self.cursor_pos)
-            .expect("Out of range index for selected item");
+        {
+            Some(select) => (select.path.clone(), select.collapsed),
+            None => return Ok(()),
+        };
+        if collapsed && self.snapshot.is_some() {
+            self.draw_warning(
+                stdout,
+                "The contents of this directory were not saved in the snapshot",
+                style::Color::DarkYellow,
+            )?;
+            block_until_key_press();
+            return Ok(());
+        }
+        if collapsed {
+            self.draw_warning(
+                stdout,
+                &format!("Scanning {}...", path.display()),
+                style::Color::Grey,
+            )?;
+            self.rescan(&path);
+        }
+        let root_dir = self.root_dir.lock().unwrap();
+        let select = match root_dir.get(&path) {
+            Some(select) => select,
+            None => return Ok(()),
+        };
         if select.contents.is_none() {
-            return;
+            return Ok(());
         }
-        if let Some(filter) = select.filter_size(SIZE_FILTER_MIN) {
-            self.selected_dir = select;
-            self.filtered = filter;
-
+        if filter_sorted(select, self.view).is_some() {
+            drop(root_dir);
+            self.selected_path = path;
             self.last_selected.push(self.cursor_pos);
             self.cursor_pos = 0;
         }
+        Ok(())
     }
 
     /// Go back to the previuous menu item
     fn go_back(&mut self) {
-        self.selected_dir = self.root_dir.find(&self.selected_dir.path);
-        self.filtered = self.selected_dir.filter_size(SIZE_FILTER_MIN).unwrap();
+        let root_dir = self.root_dir.lock().unwrap();
+        if self.selected_path == root_dir.path {
+            return;
+        }
+        let parent_path = root_dir.find(&self.selected_path).path.clone();
+        drop(root_dir);
+        self.selected_path = parent_path;
         self.cursor_pos = self.last_selected.pop().unwrap_or(0);
     }
+
+    /// Checks if the tree can be changed to match changes to the filesystem, warns if it can't
+    ///
+    /// Snapshots don't show the filesystem as it is now, and scanned directories are only added to
+    /// the tree once the scan is done
+    fn check_modifiable(&self, stdout: &mut impl Write, scanning: bool) -> Result<bool> {
+        let blocked = if self.snapshot.is_some() {
+            "Not available for snapshots, they are not scanned again"
+        } else if scanning {
+            "Not available while the directory is being scanned"
+        } else {
+            return Ok(true);
+        };
+        self.draw_warning(stdout, blocked, style::Color::DarkYellow)?;
+        block_until_key_press();
+        Ok(false)
+    }
+
+    /// Size and number of entries removed together with entries, e.g. `12.50 mb in 34 items`
+    fn removal_summary(&self, root_dir: &Dir, entries: &[&Dir]) -> String {
+        let size = root_dir.removed_size(entries, self.view.size_mode);
+        // the directories themselves are removed as well
+        let items: u64 = entries
+            .iter()
+            .map(|dir| dir.entry_count() + dir.is_dir() as u64)
+            .sum();
+        let (formated_size, format_str) = self.size_fmt.format(size);
+        format!("{:.2} {} in {} items", formated_size, format_str, items)
+    }
+
+    /// The marked entries that are still in the tree, without the ones within other marked entries
+    fn marked_entries<'d>(&self, root_dir: &'d Dir) -> Vec<&'d Dir> {
+        let mut entries: Vec<&Dir> = vec![];
+        // the paths are sorted, so entries within a marked entry directly follow it
+        for path in &self.marked {
+            if entries
+                .last()
+                .is_some_and(|last| path.starts_with(&last.path))
+            {
+                continue;
+            }
+            if let Some(entry) = root_dir.get(path) {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    /// Marks the entry under the cursor for a batch action, or unmarks it, and moves to the next
+    fn toggle_mark(&mut self) {
+        let path = match self
+            .filtered(&self.root_dir.lock().unwrap())
+            .get(self.cursor_pos)
+        {
+            Some(item) => item.path.clone(),
+            None => return,
+        };
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+        if self.cursor_pos + 1 < self.filtered_len() {
+            self.cursor_pos += 1;
+        }
+    }
+
+    /// Asks for an action and applies it to all marked entries
+    ///
+    /// Returns true if the menu is closed to print the marked paths
+    fn batch_action(&mut self, stdout: &mut impl Write, scanning: bool) -> Result<bool> {
+        let (paths, summary) = {
+            let root_dir = self.root_dir.lock().unwrap();
+            let marked = self.marked_entries(&root_dir);
+            let paths: Vec<PathBuf> = marked.iter().map(|dir| dir.path.clone()).collect();
+            (paths, self.removal_summary(&root_dir, &marked))
+        };
+        if paths.is_empty() {
+            let message = "Nothing is marked, mark entries with [space]";
+            self.draw_warning(stdout, message, style::Color::Grey)?;
+            block_until_key_press();
+            return Ok(false);
+        }
+        let message = format!(
+            "{} marked ({}): [d] delete, [x] move to trash, [m] move to a directory, [p] print the paths and exit, [c] clear the marks, any other key to cancel",
+            paths.len(),
+            summary
+        );
+        self.draw_warning(stdout, &message, style::Color::White)?;
+        let errors = match read_key() {
+            KeyCode::Char('p') => {
+                self.printed = paths;
+                return Ok(true);
+            }
+            KeyCode::Char('c') => {
+                self.marked.clear();
+                return Ok(false);
+            }
+            KeyCode::Char('d') if self.check_modifiable(stdout, scanning)? => {
+                let message = format!(
+                    "Permanently delete {} marked entries ({})? [y] to confirm, any other key to cancel",
+                    paths.len(),
+                    summary
+                );
+                self.draw_warning(stdout, &message, style::Color::Red)?;
+                if !confirm() {
+                    return Ok(false);
+                }
+                self.batch_delete(paths)
+            }
+            KeyCode::Char('x') if self.check_modifiable(stdout, scanning)? => {
+                self.batch_trash(paths)
+            }
+            KeyCode::Char('m') if self.check_modifiable(stdout, scanning)? => {
+                let target = match self.read_line(stdout, "Move the marked entries to: ")? {
+                    Some(target) if !target.is_empty() => PathBuf::from(target),
+                    _ => return Ok(false),
+                };
+                self.batch_move(paths, &target)
+            }
+            _ => return Ok(false),
+        };
+        if !errors.is_empty() {
+            let message = format!("Failed for {} entries: {}", errors.len(), errors.join(", "));
+            self.draw_warning(stdout, &message, style::Color::Red)?;
+            block_until_key_press();
+        }
+        Ok(false)
+    }
+
+    /// Deletes the marked entries, the ones that fail stay marked
+    ///
+    /// Returns the errors of the entries that failed
+    fn batch_delete(&mut self, paths: Vec<PathBuf>) -> Vec<String> {
+        let mut errors = vec![];
+        for path in paths {
+            match delete(&path) {
+                Ok(()) => {
+                    self.root_dir.lock().unwrap().remove(&path);
+                    self.unmark(&path);
+                }
+                Err(err) => {
+                    self.refresh(&path);
+                    errors.push(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Moves the marked entries to the trash, all of them are put back by undoing it
+    ///
+    /// Returns the errors of the entries that failed
+    fn batch_trash(&mut self, paths: Vec<PathBuf>) -> Vec<String> {
+        let mut errors = vec![];
+        let mut trashed = vec![];
+        for path in paths {
+            match trash(&path) {
+                Ok(entry) => {
+                    self.root_dir.lock().unwrap().remove(&path);
+                    self.refresh(&entry.trashed);
+                    self.refresh(&entry.info);
+                    self.unmark(&path);
+                    trashed.push(entry);
+                }
+                Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+            }
+        }
+        if !trashed.is_empty() {
+            self.last_trashed = trashed;
+        }
+        errors
+    }
+
+    /// Moves the marked entries into a directory
+    ///
+    /// Returns the errors of the entries that failed
+    fn batch_move(&mut self, paths: Vec<PathBuf>, target: &Path) -> Vec<String> {
+        let mut errors = vec![];
+        for path in paths {
+            match move_to(&path, target) {
+                Ok(moved) => {
+                    self.root_dir.lock().unwrap().remove(&path);
+                    self.refresh(&moved);
+                    self.unmark(&path);
+                }
+                Err(err) => {
+                    // entries copied from another filesystem can be partially deleted
+                    self.refresh(&path);
+                    errors.push(format!("{}: {}", path.display(), err));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Unmarks an entry that was removed from the tree together with the entries within it
+    fn unmark(&mut self, path: &Path) {
+        self.marked.retain(|marked| !marked.starts_with(path));
+    }
+
+    /// Reads a line typed after the prompt, None if it's cancelled with [Esc]
+    fn read_line(&self, stdout: &mut impl Write, prompt: &str) -> Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            let message = format!("{}{}", prompt, line);
+            self.draw_warning(stdout, &message, style::Color::White)?;
+            match read_key() {
+                KeyCode::Enter => return Ok(Some(line)),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Backspace => {
+                    line.pop();
+                }
+                KeyCode::Char(char) => line.push(char),
+                _ => {}
+            }
+        }
+    }
+
+    /// Deletes the entry under the cursor from the filesystem once it's confirmed and removes it
+    /// from the tree
+    fn delete_item(&mut self, stdout: &mut impl Write, scanning: bool) -> Result<()> {
+        if !self.check_modifiable(stdout, scanning)? {
+            return Ok(());
+        }
+        let (path, summary) = {
+            let root_dir = self.root_dir.lock().unwrap();
+            match self.filtered(&root_dir).get(self.cursor_pos) {
+                Some(item) => (item.path.clone(), self.removal_summary(&root_dir, &[item])),
+                None => return Ok(()),
+            }
+        };
+        let message = format!(
+            "Permanently delete {} ({})? [y] to confirm, any other key to cancel",
+            path.display(),
+            summary
+        );
+        self.draw_warning(stdout, &message, style::Color::Red)?;
+        if !confirm() {
+            return Ok(());
+        }
+        if let Err(err) = delete(&path) {
+            // the entries deleted before the error are gone from the tree as well
+            self.refresh(&path);
+            let message = format!("Failed to delete {}: {}", path.display(), err);
+            self.draw_warning(stdout, &message, style::Color::Red)?;
+            block_until_key_press();
+            return Ok(());
+        }
+        self.root_dir.lock().unwrap().remove(&path);
+        self.unmark(&path);
+        Ok(())
+    }
+
+    /// Moves the entry under the cursor to the trash and removes it from the tree, it can be put
+    /// back until the next entry is moved to the trash
+    fn trash_item(&mut self, stdout: &mut impl Write, scanning: bool) -> Result<()> {
+        if !self.check_modifiable(stdout, scanning)? {
+            return Ok(());
+        }
+        let (path, summary) = {
+            let root_dir = self.root_dir.lock().unwrap();
+            match self.filtered(&root_dir).get(self.cursor_pos) {
+                Some(item) => (item.path.clone(), self.removal_summary(&root_dir, &[item])),
+                None => return Ok(()),
+            }
+        };
+        let entry = match trash(&path) {
+            Ok(entry) => entry,
+            Err(err) => {
+                let message = format!("Failed to move {} to the trash: {}", path.display(), err);
+                self.draw_warning(stdout, &message, style::Color::Red)?;
+                block_until_key_press();
+                return Ok(());
+            }
+        };
+        self.root_dir.lock().unwrap().remove(&path);
+        self.unmark(&path);
+        // the trash can be within the scanned tree
+        self.refresh(&entry.trashed);
+        self.refresh(&entry.info);
+        self.last_trashed = vec![entry];
+        let message = format!(
+            "Moved {} to the trash ({}), [u] to undo",
+            path.display(),
+            summary
+        );
+        self.draw_warning(stdout, &message, style::Color::Grey)?;
+        block_until_key_press();
+        Ok(())
+    }
+
+    /// Puts the entries moved to the trash by the last trash action back where they were
+    fn undo_trash(&mut self, stdout: &mut impl Write, scanning: bool) -> Result<()> {
+        if !self.check_modifiable(stdout, scanning)? {
+            return Ok(());
+        }
+        if self.last_trashed.is_empty() {
+            self.draw_warning(stdout, "Nothing to undo", style::Color::Grey)?;
+            block_until_key_press();
+            return Ok(());
+        }
+        let mut errors = vec![];
+        for entry in std::mem::take(&mut self.last_trashed) {
+            match entry.restore() {
+                Ok(()) => {
+                    self.refresh(&entry.original);
+                    self.refresh(&entry.trashed);
+                    self.refresh(&entry.info);
+                }
+                Err(err) => {
+                    errors.push(format!("{}: {}", entry.original.display(), err));
+                    // kept to try again later
+                    self.last_trashed.push(entry);
+                }
+            }
+        }
+        if !errors.is_empty() {
+            let message = format!("Failed to restore {}", errors.join(", "));
+            self.draw_warning(stdout, &message, style::Color::Red)?;
+            block_until_key_press();
+        }
+        Ok(())
+    }
+
+    /// Scans an entry again after it was changed, it's removed from the tree if it doesn't exist
+    /// anymore, entries whose directory is not in the tree are skipped
+    fn refresh(&mut self, path: &Path) {
+        let (root, ignored) = {
+            let root_dir = self.root_dir.lock().unwrap();
+            let parent = match path.parent().and_then(|parent| root_dir.get(parent)) {
+                Some(parent) if parent.contents.is_some() => parent,
+                _ => return,
+            };
+            // everything below an ignored directory is ignored
+            let ignored = parent.ignored || root_dir.get(path).is_some_and(|old| old.ignored);
+            (root_dir.path.clone(), ignored)
+        };
+        let entry = scan_entry(&root, path.to_path_buf(), &self.scan_options);
+        let mut root_dir = self.root_dir.lock().unwrap();
+        match entry {
+            Some(mut entry) => {
+                if ignored {
+                    entry.mark_ignored();
+                }
+                root_dir.insert(entry);
+            }
+            None => {
+                root_dir.remove(path);
+            }
+        }
+        drop(root_dir);
+        self.rescanned(path);
+    }
+
+    /// Scans the listed directory again and replaces it in the tree, to pick up the changes made
+    /// since it was scanned
+    ///
+    /// A directory that doesn't exist anymore is removed from the tree and its parent is listed
+    fn rescan_selected(&mut self, stdout: &mut impl Write, scanning: bool) -> Result<()> {
+        if !self.check_modifiable(stdout, scanning)? {
+            return Ok(());
+        }
+        let path = self.selected_path.clone();
+        let is_root = path == self.root_dir.lock().unwrap().path;
+        let removed =
+            fs::symlink_metadata(&path).is_err_and(|err| err.kind() == ErrorKind::NotFound);
+        if removed && !is_root {
+            self.root_dir.lock().unwrap().remove(&path);
+            self.go_back();
+            return Ok(());
+        }
+        self.draw_warning(
+            stdout,
+            &format!("Scanning {}...", path.display()),
+            style::Color::Grey,
+        )?;
+        self.rescan(&path);
+        Ok(())
+    }
+
+    /// Scans a directory again and replaces it in the tree
+    fn rescan(&mut self, path: &Path) {
+        let root = self.root_dir.lock().unwrap().path.clone();
+        let (kind, target, ignored) = match self.root_dir.lock().unwrap().get(path) {
+            Some(old) => (old.kind, old.target.clone(), old.ignored),
+            None => return,
+        };
+        let mut subtree = make_dir_tree_within(&root, path.to_path_buf(), &self.scan_options);
+        subtree.kind = kind;
+        subtree.target = target;
+        if ignored {
+            subtree.mark_ignored();
+        }
+        self.root_dir.lock().unwrap().splice(subtree);
+        self.rescanned(path);
+    }
+
+    /// Lets the watcher know about the directories found by scanning an entry again
+    fn rescanned(&self, path: &Path) {
+        if let Some(watch) = &self.watch {
+            watch.rescanned(path.to_path_buf());
+        }
+    }
+
+    /// Opens the errors view with the errors of the whole tree
+    fn show_errors(&mut self, stdout: &mut impl Write) -> Result<()> {
+        let report = ScanReport::new(&self.root_dir.lock().unwrap());
+        if report.errors.is_empty() {
+            self.draw_warning(stdout, "No read errors", style::Color::Grey)?;
+            block_until_key_press();
+            return Ok(());
+        }
+        let title = format!("{} entries could not be read", report.errors.len());
+        let lines = report
+            .errors
+            .into_iter()
+            .map(|error| {
+                let line = format!("{} - {}", error.path.display(), error.message);
+                (line, style::Color::DarkRed)
+            })
+            .collect();
+        self.overlay = Some(Overlay::new(title, lines, 'e'));
+        Ok(())
+    }
+
+    /// Opens the list of the users and groups owning the selected directory
+    fn show_owners(&mut self) {
+        let names = OwnerNames::load();
+        let root_dir = self.root_dir.lock().unwrap();
+        let selected_dir = self.selected_dir(&root_dir);
+        let mode = self.view.size_mode;
+        let mut lines = vec![];
+        for kind in [OwnerKind::User, OwnerKind::Group] {
+            let usage = selected_dir
+                .usage_by_owner(kind, mode)
+                .into_iter()
+                .map(|(id, usage)| (names.name(kind, id), usage))
+                .collect();
+            lines.extend(self.usage_lines(&format!("by {}:", kind.label()), usage));
+        }
+        let title = format!(
+            "Owners of {} ({})",
+            selected_dir.path.display(),
+            mode.label()
+        );
+        drop(root_dir);
+        self.overlay = Some(Overlay::new(title, lines, 'w'));
+    }
+
+    /// Opens the breakdown of the selected directory by file category and extension
+    fn show_types(&mut self) {
+        let root_dir = self.root_dir.lock().unwrap();
+        let selected_dir = self.selected_dir(&root_dir);
+        let mode = self.view.size_mode;
+        let categories = selected_dir
+            .usage_by_category(mode)
+            .into_iter()
+            .map(|(category, usage)| (category.label().to_string(), usage))
+            .collect();
+        let extensions = selected_dir
+            .usage_by_extension(mode)
+            .into_iter()
+            .map(|(extension, usage)| (extension_label(extension), usage))
+            .collect();
+        let mut lines = self.usage_lines("by category:", categories);
+        lines.extend(self.usage_lines("by extension:", extensions));
+        let title = format!(
+            "File types in {} ({})",
+            selected_dir.path.display(),
+            mode.label()
+        );
+        drop(root_dir);
+        self.overlay = Some(Overlay::new(title, lines, 't'));
+    }
+
+    /// Opens the list of every key of the menu
+    fn show_keys(&mut self) {
+        let max_len = KEYS.iter().map(|(key, _)| key.chars().count()).max();
+        let lines = KEYS
+            .iter()
+            .map(|(key, action)| {
+                let line = format!(
+                    "{:<max_len$} - {}",
+                    key,
+                    action,
+                    max_len = max_len.unwrap_or(0)
+                );
+                (line, style::Color::Grey)
+            })
+            .collect();
+        self.overlay = Some(Overlay::new("Keys".to_string(), lines, '?'));
+    }
+
+    /// Lines of a heading followed by the aligned usage of every group
+    fn usage_lines(
+        &self,
+        heading: &str,
+        usage: Vec<(String, Usage)>,
+    ) -> Vec<(String, style::Color)> {
+        let max_len = usage.iter().map(|(name, _)| name.chars().count()).max();
+        let mut lines = vec![(heading.to_string(), style::Color::White)];
+        for (name, usage) in usage {
+            let line = format!(
+                "  {:<max_len$} - {}",
+                name,
+                usage.display(&self.size_fmt, self.view.size_mode),
+                max_len = max_len.unwrap_or(0)
+            );
+            lines.push((line, style::Color::Grey));
+        }
+        lines
+    }
+
+    /// Changes the shown sizes, re-filtering and re-sorting the current directory
+    fn change_view(&mut self, stdout: &mut impl Write, view: View) -> Result<()> {
+        let root_dir = self.root_dir.lock().unwrap();
+        if filter_sorted(self.selected_dir(&root_dir), view).is_some() {
+            drop(root_dir);
+            self.view = view;
+            self.cursor_pos = 0;
+        } else {
+            drop(root_dir);
+            self.draw_warning(
+                stdout,
+                &format!("No files/dirs above 1mb by {}", view.label()),
+                style::Color::Red,
+            )?;
+            block_until_key_press();
+        }
+        Ok(())
+    }
+}
+
+/// Calculates the index range to print menu elements
+fn calculate_index_bounds(
+    cursor_pos: usize,
+    items_len: usize,
+    reserved_lines: usize,
+) -> (usize, usize) {
+    let items_len = items_len.saturating_sub(1);
+    let (terminal_width, terminal_height) = terminal::size().unwrap();
+    let (_, terminal_height) = (
+        terminal_width as usize - 1,
+        terminal_height as usize - 3 - reserved_lines,
+    );
+    if cursor_pos <= (terminal_height / 2) {
+        (0, terminal_height)
+    } else if items_len > cursor_pos + (terminal_height / 2) {
+        (
+            cursor_pos - (terminal_height / 2) - 1,
+            cursor_pos + (terminal_height / 2),
+        )
+    } else if items_len > terminal_height {
+        (
+            cursor_pos - (terminal_height - (items_len - cursor_pos)),
+            items_len,
+        )
+    } else {
+        (0, items_len)
+    }
+}
+
+/// Filters the contents of dir above the minimum size and sorts them from biggest to smallest by
+/// the sort order of the view, entries that could not be read are always kept
+fn filter_sorted(dir: &Dir, view: View) -> Option<Vec<&Dir>> {
+    let mut filtered: Vec<&Dir> = dir
+        .contents
+        .as_ref()?
+        .iter()
+        .filter(|dir| {
+            view.size(dir) > SIZE_FILTER_MIN
+                || dir.error.is_some()
+                || dir
+                    .delta(view.size_mode)
+                    .is_some_and(|delta| delta.unsigned_abs() > SIZE_FILTER_MIN)
+        })
+        .collect();
+    if filtered.is_empty() {
+        return None;
+    }
+    filtered.sort_by_key(|dir| Reverse(view.sort_key(dir)));
+    Some(filtered)
+}
+
+/// Formats how long ago a time was in the biggest fitting unit, e.g. `3d ago`
+fn format_age(time: SystemTime) -> String {
+    let secs = match SystemTime::now().duration_since(time) {
+        Ok(age) => age.as_secs(),
+        Err(_) => return "future".to_string(),
+    };
+    let (minute, hour, day) = (60, 60 * 60, 24 * 60 * 60);
+    if secs < minute {
+        "now".to_string()
+    } else if secs < hour {
+        format!("{}m ago", secs / minute)
+    } else if secs < day {
+        format!("{}h ago", secs / hour)
+    } else if secs < 60 * day {
+        format!("{}d ago", secs / day)
+    } else if secs < 365 * day {
+        format!("{}mo ago", secs / (30 * day))
+    } else {
+        format!("{}y ago", secs / (365 * day))
+    }
+}
+
+/// Calculates the maximum directory name length of Vec<&Dir>
+fn calculate_max_len(filtered: &[&Dir]) -> usize {
+    filtered
+        .iter()
+        .map(|dir| dir.menu_name().chars().count())
+        .fold(0, |acc, l| if l > acc { l } else { acc })
+}
+
+/// Waits for a key press and returns the pressed key
+fn read_key() -> KeyCode {
+    loop {
+        if let Ok(Event::Key(key)) = crossterm::event::read() {
+            if let KeyEventKind::Press = key.kind {
+                return key.code;
+            }
+        };
+    }
+}
+
+/// Waits for a key press, returns true if it was [y]
+fn confirm() -> bool {
+    read_key() == KeyCode::Char('y')
 }
 
 fn block_until_key_press() {
@@ -0,0 +1,139 @@
+use crate::structs::{sort_usage, Dir, SizeMode, Usage};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Whether entries are grouped by the user or the group owning them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnerKind {
+    User,
+    Group,
+}
+
+impl OwnerKind {
+    pub fn label(&self) -> &str {
+        match self {
+            OwnerKind::User => "user",
+            OwnerKind::Group => "group",
+        }
+    }
+}
+
+/// Names of the users and groups, read from `/etc/passwd` and `/etc/group`
+#[derive(Debug, Clone, Default)]
+pub struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+    /// Reads the names of the local users and groups, missing files leave the names unresolved
+    pub fn load() -> Self {
+        let read = |path: &str| {
+            fs::read_to_string(Path::new(path))
+                .map(|contents| parse_names(&contents))
+                .unwrap_or_default()
+        };
+        Self {
+            users: read("/etc/passwd"),
+            groups: read("/etc/group"),
+        }
+    }
+
+    /// Name of a user or group, the id if it has no name
+    pub fn name(&self, kind: OwnerKind, id: Option<u32>) -> String {
+        let names = match kind {
+            OwnerKind::User => &self.users,
+            OwnerKind::Group => &self.groups,
+        };
+        match id {
+            Some(id) => names.get(&id).cloned().unwrap_or_else(|| id.to_string()),
+            None => "unknown".to_string(),
+        }
+    }
+}
+
+/// Parses the `name:password:id:...` lines of `/etc/passwd` and `/etc/group` into names by id
+fn parse_names(contents: &str) -> HashMap<u32, String> {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+impl Dir {
+    /// Sums up the usage of the files in the tree by the user or group owning them, sorted from
+    /// the biggest to the smallest owner in the given size mode
+    ///
+    /// The owner is None for entries whose owner is not known (e.g. on windows)
+    pub fn usage_by_owner(&self, kind: OwnerKind, mode: SizeMode) -> Vec<(Option<u32>, Usage)> {
+        let usage = self.usage_by(|dir| match kind {
+            OwnerKind::User => dir.uid,
+            OwnerKind::Group => dir.gid,
+        });
+        sort_usage(usage, mode)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::owners::{parse_names, OwnerKind, OwnerNames};
+    use crate::structs::{make_file, Dir, SizeMode};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_usage_by_owner() {
+        let file = |name: &str, size: u64, uid: u32, gid: u32| {
+            let mut file = make_file(name, size);
+            (file.uid, file.gid) = (Some(uid), Some(gid));
+            file
+        };
+        let mut linked = file("/data/c/link", 100, 1000, 100);
+        linked.hard_link = true;
+        let sub_dir = Dir::from_contents(
+            PathBuf::from("/data/c"),
+            vec![file("/data/c/x", 30, 0, 100), linked],
+        );
+        let tree = Dir::from_contents(
+            PathBuf::from("/data"),
+            vec![
+                file("/data/a", 100, 1000, 100),
+                file("/data/b", 50, 1001, 100),
+                sub_dir,
+            ],
+        );
+
+        let users = tree.usage_by_owner(OwnerKind::User, SizeMode::Apparent);
+        let sizes: Vec<(Option<u32>, u64, u64)> = users
+            .iter()
+            .map(|(uid, usage)| (*uid, usage.size, usage.file_count))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![(Some(1000), 100, 1), (Some(1001), 50, 1), (Some(0), 30, 1)]
+        );
+        let groups = tree.usage_by_owner(OwnerKind::Group, SizeMode::Apparent);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].1.size, tree.size);
+    }
+
+    #[test]
+    fn test_owner_names() {
+        let users = parse_names("root:x:0:0:root:/root:/bin/bash\n# comment\nalice:x:1000:1000::/home/alice:/bin/sh\nbroken\n");
+        assert_eq!(users.len(), 2);
+        let names = OwnerNames {
+            users,
+            ..Default::default()
+        };
+        assert_eq!(names.name(OwnerKind::User, Some(1000)), "alice");
+        assert_eq!(names.name(OwnerKind::User, Some(1234)), "1234");
+        assert_eq!(names.name(OwnerKind::Group, Some(0)), "0");
+        assert_eq!(names.name(OwnerKind::User, None), "unknown");
+    }
+}
@@ -1,10 +1,18 @@
-use crate::structs::Dir;
+use crate::filter::{IgnoreStack, PathFilter};
+use crate::structs::{allocated_size, changed_time, Dir, EntryKind, SharedDir};
 use log::{debug, warn};
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
-use std::io::ErrorKind;
+use std::io::{self, ErrorKind};
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 /// Scans a directory recursively and finds all files contained within the directory and makes a directory tree (Dir)
 ///
@@ -25,117 +33,1156 @@ use std::time::{Duration, Instant};
 /// }
 /// ```
 pub fn make_dir_tree(path_to_dir: PathBuf) -> Dir {
-    let mut contents = Vec::new();
-    let r_dir = match fs::read_dir(&path_to_dir) {
-        Ok(dir) => dir,
-        Err(err) => {
-            warn!(
-                "Error occured when trying to read {} error: {}",
-                path_to_dir.display(),
-                err
+    make_dir_tree_with(path_to_dir, &ScanOptions::default())
+}
+
+/// Scans a directory recursively in parallel and makes a directory tree (Dir)
+///
+/// Every subdirectory is scanned by a recursive call that splits its own entries across the rayon
+/// thread pool, so work is stolen at every depth of the tree and not only on the first level.
+/// Produces the same tree as [`make_dir_tree`].
+///
+/// Args:
+/// - path_to_dir - The path to the directory
+pub fn make_dir_tree_parallel(path_to_dir: PathBuf) -> Dir {
+    make_dir_tree_parallel_with(path_to_dir, &ScanOptions::default())
+}
+
+/// Same as [`make_dir_tree`] but with the given scan options
+pub fn make_dir_tree_with(path_to_dir: PathBuf, options: &ScanOptions) -> Dir {
+    Scan::new(options, false).root(path_to_dir, None)
+}
+
+/// Same as [`make_dir_tree_parallel`] but with the given scan options
+pub fn make_dir_tree_parallel_with(path_to_dir: PathBuf, options: &ScanOptions) -> Dir {
+    Scan::new(options, true).root(path_to_dir, None)
+}
+
+/// Scans the directory at the root of a shared tree in parallel, building the tree up while it
+/// is being scanned so it can be shown at the same time
+///
+/// When a directory is listed its subdirectories are added to the tree marked as pending, every
+/// directory is replaced by its scanned contents once it is done, adding its sizes to all of its
+/// parents. The directories are added in batches every few milliseconds, so the tree is not
+/// locked for every directory. Blocks until the scan is done, so it is usually run on its own
+/// thread.
+///
+/// Args:
+/// - tree - the tree to build, its path is the directory that is scanned
+/// - options - the scan options
+pub fn make_dir_tree_live(tree: SharedDir, options: &ScanOptions) {
+    let path_to_dir = tree.lock().unwrap().path.clone();
+    let mut scan = Scan::new(options, true);
+    scan.live = Some(LiveTree::new(tree));
+    scan.root(path_to_dir, None);
+}
+
+/// Scans a single entry of a tree that was already scanned, to update the tree after the entry
+/// changed
+///
+/// `root` is the path of the tree, the entry is collapsed at the same depth and matched against
+/// the same ignore files as in a full scan. Returns None if the entry doesn't exist anymore or is
+/// not scanned with these options (excluded or modified too recently).
+pub fn scan_entry(root: &Path, path: PathBuf, options: &ScanOptions) -> Option<Dir> {
+    let is_dir = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata.is_dir(),
+        Err(err) if err.kind() == ErrorKind::NotFound => return None,
+        Err(_) => false,
+    };
+    if options.filter.is_excluded(&path, is_dir) {
+        return None;
+    }
+    let mut scan = Scan::new(options, true);
+    if options.one_file_system {
+        // the parent is part of the scanned tree, so it's on the filesystem of the root, the root
+        // itself defines that filesystem
+        let parent = if path == root {
+            Some(root)
+        } else {
+            path.parent()
+        };
+        let metadata = parent.and_then(|parent| fs::metadata(parent).ok());
+        scan.root_device = metadata.as_ref().and_then(device_id);
+    }
+    scan.set_root(root);
+    let depth = path
+        .strip_prefix(root)
+        .map_or(0, |path| path.components().count());
+    let mut entry = with_parent_ignores(root, &path, options, |ignores| {
+        scan.entry(path.clone(), ignores, depth, None, false)
+    })?;
+    scan.finish(&mut entry);
+    Some(entry)
+}
+
+/// Scans a directory of a tree that was already scanned again, in parallel
+///
+/// Unlike [`make_dir_tree_parallel_with`] the ignore files of the parents of the directory up to
+/// `root`, the path of the tree, are read as well. The maximum depth counts from the directory
+/// itself, so collapsed directories can be scanned with it.
+pub fn make_dir_tree_within(root: &Path, path_to_dir: PathBuf, options: &ScanOptions) -> Dir {
+    with_parent_ignores(root, &path_to_dir, options, |ignores| {
+        let mut scan = Scan::new(options, true);
+        scan.set_root(root);
+        scan.root(path_to_dir.clone(), ignores)
+    })
+}
+
+/// Calls `f` with the ignore rules of the directories from `root` down to the parent of `path`,
+/// on top of the global git excludes, None if ignored entries are not detected
+fn with_parent_ignores<R>(
+    root: &Path,
+    path: &Path,
+    options: &ScanOptions,
+    f: impl FnOnce(Option<&IgnoreStack>) -> R,
+) -> R {
+    if !options.gitignore {
+        return f(None);
+    }
+    let mut parents: Vec<&Path> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|parent| parent.starts_with(root))
+        .collect();
+    parents.reverse();
+    IgnoreStack::global().push_dirs(&parents, |ignores| f(Some(ignores)))
+}
+
+/// How symbolic links are treated while scanning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// symlinks are leaf entries with the size of the link itself
+    #[default]
+    Never,
+    /// symlinks are resolved and directories they point to are scanned, each directory and file is
+    /// only counted once so link cycles and links into already scanned parts of the tree are cut off
+    Follow,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(Self::Never),
+            "follow" => Ok(Self::Follow),
+            _ => Err("Unrecognized symlink policy\nexpected one of: [never, follow]".to_string()),
+        }
+    }
+}
+
+/// Snapshot of the progress of a running scan
+///
+/// contains:
+/// - files - the number of files (and other non directory entries) seen so far
+/// - dirs - the number of directories read so far
+/// - bytes - the apparent size of the files seen so far
+/// - errors - the number of entries that could not be read
+/// - current_path - the directory that was read last
+#[derive(Debug, Clone, Default)]
+pub struct ScanProgress {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub current_path: PathBuf,
+}
+
+/// Callback that is called with the progress of a scan after every directory that is read
+///
+/// It is called from the scanning threads, so it should return quickly
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&ScanProgress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&ScanProgress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ProgressCallback")
+    }
+}
+
+/// An entry that could not be read while scanning
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+/// Summary of the problems of a scan, collected from the scanned tree
+///
+/// contains:
+/// - errors - every entry that could not be read and why, in the order of the tree
+/// - incomplete - the scan was cancelled before it was done
+#[derive(Debug, Clone, Default)]
+pub struct ScanReport {
+    pub errors: Vec<ScanError>,
+    pub incomplete: bool,
+}
+
+impl ScanReport {
+    /// Collects the report of a scanned tree
+    pub fn new(dir: &Dir) -> Self {
+        let mut report = Self {
+            errors: Vec::with_capacity(dir.error_count as usize),
+            incomplete: dir.incomplete,
+        };
+        report.collect(dir);
+        report
+    }
+
+    fn collect(&mut self, dir: &Dir) {
+        if let Some(message) = &dir.error {
+            self.errors.push(ScanError {
+                path: dir.path.clone(),
+                message: message.clone(),
+            });
+        }
+        // the errors below a collapsed directory are kept among its hidden entries
+        for hidden in dir.hidden.iter().filter(|hidden| hidden.error.is_some()) {
+            self.collect(hidden);
+        }
+        // only the subtrees that have errors are walked
+        for sub_dir in dir.contents.iter().flatten() {
+            if sub_dir.error_count > 0 {
+                self.collect(sub_dir);
+            }
+        }
+    }
+}
+
+/// Token that stops a running scan when it is cancelled
+///
+/// Clones share the same state, so a scan can be cancelled from another thread (a Ctrl-C handler,
+/// a timer, the menu). The scan still returns the tree scanned so far, directories that were not
+/// fully scanned are flagged as incomplete.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops the scans using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Cancels the token once the timeout is over, from a background thread
+    pub fn cancel_after(&self, timeout: Duration) {
+        let token = self.clone();
+        thread::spawn(move || {
+            thread::sleep(timeout);
+            token.cancel();
+        });
+    }
+}
+
+/// Tree of an earlier scan of the same directory that is reused for the parts that didn't change
+///
+/// Directories whose modification and change times are the same as in the cached tree are not
+/// listed again, their files are taken from the cache without reading their metadata and only
+/// their subdirectories are checked. Adding, removing or replacing an entry changes the times of
+/// its directory, files that are rewritten in place keep their cached size until their directory
+/// changes. The cached tree is only used by scans with the same [`ScanOptions::fingerprint`].
+#[derive(Clone)]
+pub struct ScanCache {
+    tree: Arc<Dir>,
+    fingerprint: String,
+}
+
+impl ScanCache {
+    /// Args:
+    /// - tree - the tree of the earlier scan
+    /// - fingerprint - the fingerprint of the options the tree was scanned with
+    pub fn new(tree: Dir, fingerprint: String) -> Self {
+        Self {
+            tree: Arc::new(tree),
+            fingerprint,
+        }
+    }
+}
+
+impl fmt::Debug for ScanCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ScanCache({})", self.tree.path.display())
+    }
+}
+
+/// Options that change how a directory tree is scanned
+///
+/// contains:
+/// - symlinks - how symbolic links are treated
+/// - one_file_system - don't descend into directories on other filesystems than the root (`du -x`)
+/// - filter - glob patterns of entries that are skipped
+/// - summarize_excluded - also scan the excluded entries to sum up their size on the parent directory
+/// - gitignore - mark the entries ignored by `.gitignore`, `.ignore` and the global git excludes
+/// - max_depth - directories deeper than this are collapsed, their sizes are still summed up (`du -d`)
+/// - progress - called with the progress of the scan
+/// - cancel - stops the scan when it is cancelled
+/// - modified_before - only files last modified before this time are scanned, to find stale data
+/// - cache - an earlier scan whose unchanged directories are reused
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    pub symlinks: SymlinkPolicy,
+    pub one_file_system: bool,
+    pub filter: PathFilter,
+    pub summarize_excluded: bool,
+    pub gitignore: bool,
+    pub max_depth: Option<usize>,
+    pub progress: Option<ProgressCallback>,
+    pub cancel: Option<CancelToken>,
+    pub modified_before: Option<SystemTime>,
+    pub cache: Option<ScanCache>,
+}
+
+impl ScanOptions {
+    /// Describes the options that decide which entries end up in the scanned tree and how, only a
+    /// tree scanned with the same fingerprint is reused as a cache
+    ///
+    /// The time only older files are scanned from is left out as it moves with every scan, the
+    /// files of cached trees are checked against it again.
+    pub fn fingerprint(&self) -> String {
+        format!(
+            "symlinks={:?} one_file_system={} {} summarize_excluded={} gitignore={} max_depth={:?}",
+            self.symlinks,
+            self.one_file_system,
+            self.filter.fingerprint(),
+            self.summarize_excluded,
+            self.gitignore,
+            self.max_depth
+        )
+    }
+}
+
+/// The entries of a directory that are scanned
+///
+/// contains:
+/// - entries - the paths of the entries that are scanned
+/// - excluded - the paths of the entries skipped by the filter
+/// - sub_dirs - the entries that are directories, only collected for live scans
+/// - error - the error that stopped the listing, the entries listed before it are kept
+#[derive(Default)]
+struct Listing {
+    entries: Vec<PathBuf>,
+    excluded: Vec<PathBuf>,
+    sub_dirs: Vec<PathBuf>,
+    error: Option<io::Error>,
+}
+
+/// Checks if a directory can be taken from the cached tree without listing it again: it was
+/// fully scanned and its modification and change times are still the same
+fn unchanged(cached: &Dir, metadata: &fs::Metadata) -> bool {
+    cached.kind == EntryKind::Directory
+        && cached.contents.is_some()
+        && !cached.collapsed
+        && !cached.incomplete
+        && cached.error.is_none()
+        && cached.modified.is_some()
+        && cached.modified == metadata.modified().ok()
+        && cached.changed == changed_time(metadata)
+}
+
+/// Checks if an entry of an unchanged directory can be reused without reading its metadata,
+/// directories are checked on their own and entries with errors are read again
+fn reusable(cached: &Dir) -> bool {
+    !cached.is_dir() && cached.error.is_none()
+}
+
+/// How often the directories listed and scanned in the meantime are added to a live tree
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A change to a live tree, queued until it is added together with the ones queued after it
+enum LiveUpdate {
+    /// a directory was listed, its subdirectories are added as pending
+    Listing(PathBuf, Vec<PathBuf>),
+    /// a directory was fully scanned and replaces the pending one
+    Scanned(Box<Dir>),
+}
+
+/// The updates waiting to be added to a live tree
+struct LiveQueue {
+    updates: Vec<LiveUpdate>,
+    flushed: Instant,
+}
+
+/// Tree that is built up while it is being scanned
+///
+/// The listed and scanned directories are queued and added to the tree in batches, so the
+/// scanning threads only rarely take the lock of the tree, which is also held while it is shown.
+struct LiveTree {
+    tree: SharedDir,
+    queue: Mutex<LiveQueue>,
+    /// position of every pending directory within the contents of its parent, so directories are
+    /// found without searching through their parents, only used while the tree is locked
+    positions: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl LiveTree {
+    fn new(tree: SharedDir) -> Self {
+        Self {
+            tree,
+            queue: Mutex::new(LiveQueue {
+                updates: vec![],
+                flushed: Instant::now(),
+            }),
+            positions: Mutex::default(),
+        }
+    }
+
+    /// Queues an update, the queued updates are added to the tree once they waited long enough
+    fn push(&self, update: LiveUpdate) {
+        let due = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.updates.push(update);
+            queue.flushed.elapsed() >= PUBLISH_INTERVAL
+        };
+        // a tree that is locked right now gets the updates with the next batch
+        if due {
+            if let Ok(mut tree) = self.tree.try_lock() {
+                self.flush_into(&mut tree);
+            }
+        }
+    }
+
+    /// Adds all of the queued updates to the tree
+    fn flush(&self) {
+        let mut tree = self.tree.lock().unwrap();
+        self.flush_into(&mut tree);
+    }
+
+    /// The queue is only taken while the tree is locked, so the batches are added in order
+    fn flush_into(&self, tree: &mut Dir) {
+        let updates = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.flushed = Instant::now();
+            std::mem::take(&mut queue.updates)
+        };
+        let mut positions = self.positions.lock().unwrap();
+        for update in updates {
+            let path = match &update {
+                LiveUpdate::Listing(path, _) => path,
+                LiveUpdate::Scanned(dir) => &dir.path,
+            };
+            let found = match locate(tree, &positions, path) {
+                Some(found) => found,
+                None => {
+                    debug!("{} is not in the live tree", path.display());
+                    continue;
+                }
+            };
+            match update {
+                LiveUpdate::Listing(_, sub_dirs) => {
+                    let pending = sub_dirs
+                        .into_iter()
+                        .enumerate()
+                        .map(|(position, path)| {
+                            positions.insert(path.clone(), position);
+                            let mut sub_dir = Dir::new(0, path, None, EntryKind::Directory);
+                            sub_dir.pending = true;
+                            sub_dir
+                        })
+                        .collect();
+                    tree.update_at(&found, |dir| {
+                        dir.contents = Some(pending);
+                        dir.pending = true;
+                        dir.recalculate();
+                    });
+                }
+                LiveUpdate::Scanned(scanned) => {
+                    tree.update_at(&found, |dir| {
+                        // nothing below a scanned directory is looked up anymore
+                        for sub_dir in dir.contents.iter().flatten() {
+                            positions.remove(&sub_dir.path);
+                        }
+                        dir.merge_from(*scanned);
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Finds a directory of a live tree by the positions of its parents, returns its position within
+/// the contents of each of its parents
+fn locate(tree: &Dir, positions: &HashMap<PathBuf, usize>, path: &Path) -> Option<Vec<usize>> {
+    let mut parent = tree.path.clone();
+    path.strip_prefix(&tree.path)
+        .ok()?
+        .components()
+        .map(|component| {
+            parent.push(component);
+            positions.get(&parent).copied()
+        })
+        .collect()
+}
+
+/// State shared between all the directories of a single scan
+#[derive(Default)]
+struct ScanState {
+    /// (device, inode) pairs of the files with multiple hard links that were already counted
+    seen_inodes: Mutex<HashSet<(u64, u64)>>,
+    /// (device, inode) pairs of the directories that were scanned, only tracked when following symlinks
+    seen_dirs: Mutex<HashSet<(u64, u64)>>,
+    /// symlinks to directories outside of the tree, followed once the tree is scanned
+    links: Mutex<Vec<LinkToFollow>>,
+    files: AtomicU64,
+    dirs: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// A symlink to a directory outside of the scanned tree
+///
+/// The links are followed once the tree is scanned, in the order of their paths, so which of the
+/// links to the same directory counts it doesn't depend on the order the threads get to them.
+struct LinkToFollow {
+    path: PathBuf,
+    depth: usize,
+    ignored: bool,
+}
+
+/// Identifies a file by its (device, inode) pair
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Identifies a file by its (device, inode) pair
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Id of the device (filesystem) a file is on
+#[cfg(unix)]
+pub(crate) fn device_id(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+/// Id of the device (filesystem) a file is on
+#[cfg(not(unix))]
+pub(crate) fn device_id(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Number of hard links pointing to a file
+#[cfg(unix)]
+fn link_count(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.nlink()
+}
+
+/// Number of hard links pointing to a file
+#[cfg(not(unix))]
+fn link_count(_metadata: &fs::Metadata) -> u64 {
+    1
+}
+
+/// A single scan of a directory tree
+struct Scan<'a> {
+    options: &'a ScanOptions,
+    state: ScanState,
+    parallel: bool,
+    /// device of the root directory, set when staying on one filesystem
+    root_device: Option<u64>,
+    /// path of the scanned tree and its canonical path, symlinks that point within it are not
+    /// followed as their targets are counted in the tree
+    root: Option<(PathBuf, PathBuf)>,
+    /// tree that is built up while scanning
+    live: Option<LiveTree>,
+}
+
+impl<'a> Scan<'a> {
+    fn new(options: &'a ScanOptions, parallel: bool) -> Self {
+        Self {
+            options,
+            state: ScanState::default(),
+            parallel,
+            root_device: None,
+            root: None,
+            live: None,
+        }
+    }
+
+    /// Sets the path of the tree the scanned directories are in
+    fn set_root(&mut self, root: &Path) {
+        let canonical = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        self.root = Some((root.to_path_buf(), canonical));
+    }
+
+    /// Checks if a symlink points within the scanned tree
+    fn within_tree(&self, path: &Path) -> bool {
+        match (&self.root, fs::canonicalize(path)) {
+            (Some((_, root)), Ok(target)) => target.starts_with(root),
+            _ => false,
+        }
+    }
+
+    fn following(&self) -> bool {
+        self.options.symlinks == SymlinkPolicy::Follow
+    }
+
+    /// Checks if a file was modified after the time only older files are scanned from
+    fn too_new(&self, modified: Option<SystemTime>) -> bool {
+        match (self.options.modified_before, modified) {
+            (Some(modified_before), Some(modified)) => modified >= modified_before,
+            _ => false,
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Scans the root directory of the tree
+    ///
+    /// `ignores` are the ignore rules of the parents of the directory, only the global git
+    /// excludes are used if there are none
+    fn root(mut self, path_to_dir: PathBuf, ignores: Option<&IgnoreStack>) -> Dir {
+        if self.root.is_none() {
+            self.set_root(&path_to_dir);
+        }
+        let metadata = fs::metadata(&path_to_dir).ok();
+        if let Some(metadata) = &metadata {
+            if self.following() {
+                self.first_dir(metadata);
+            }
+            if self.options.one_file_system {
+                self.root_device = device_id(metadata);
+            }
+        }
+        let global = (self.options.gitignore && ignores.is_none()).then(IgnoreStack::global);
+        let ignores = ignores.or(global.as_ref());
+        let cached = self.options.cache.as_ref().filter(|cache| {
+            let fingerprint = self.options.fingerprint();
+            if cache.fingerprint != fingerprint {
+                warn!(
+                    "Not using the cache, it was scanned with other options: {} instead of {}",
+                    cache.fingerprint, fingerprint
+                );
+            }
+            cache.fingerprint == fingerprint
+        });
+        let cached = cached
+            .map(|cache| cache.tree.as_ref())
+            .filter(|cached| cached.path == path_to_dir);
+        let unchanged = matches!((cached, &metadata), (Some(cached), Some(metadata)) if unchanged(cached, metadata));
+        let live = self.live.is_some();
+        let mut dir = self.dir(path_to_dir, ignores, 0, cached, unchanged, live);
+        if let Some(metadata) = &metadata {
+            dir.set_metadata(metadata);
+        }
+        let mut dir = self.publish(dir);
+        if let Some(live) = &self.live {
+            live.flush();
+        }
+        self.finish(&mut dir);
+        dir
+    }
+
+    /// Completes a scanned tree, or the live tree if there is one: follows the symlinks that were
+    /// put off and counts every inode that was reached through several paths once
+    fn finish(&self, tree: &mut Dir) {
+        self.follow_links(tree);
+        match &self.live {
+            Some(live) => live.tree.lock().unwrap().count_links_once(),
+            None => tree.count_links_once(),
+        }
+    }
+
+    /// Follows the symlinks to directories outside of the tree that were found while scanning it
+    /// and replaces their pending nodes, links found within the followed directories included
+    fn follow_links(&self, tree: &mut Dir) {
+        loop {
+            let link = {
+                let mut links = self.state.links.lock().unwrap();
+                let first = (0..links.len()).min_by(|&a, &b| links[a].path.cmp(&links[b].path));
+                first.map(|index| links.swap_remove(index))
+            };
+            let link = match link {
+                Some(link) => link,
+                None => break,
+            };
+            // the tree isn't locked while the directory is scanned
+            let followed = self.follow(&link);
+            let mut live_tree = self.live.as_ref().map(|live| live.tree.lock().unwrap());
+            let tree = live_tree.as_deref_mut().unwrap_or(&mut *tree);
+            tree.update_hidden(&link.path, |pending| *pending = followed);
+            if self.cancelled() {
+                tree.incomplete = true;
+            }
+        }
+    }
+
+    /// Scans the directory a symlink points to, unless it was already scanned
+    fn follow(&self, link: &LinkToFollow) -> Dir {
+        let path = link.path.clone();
+        let mut followed = match fs::metadata(&path) {
+            _ if self.cancelled() => {
+                let mut followed = Dir::new(0, path, None, EntryKind::Symlink);
+                followed.incomplete = true;
+                followed
+            }
+            Ok(metadata) if self.first_dir(&metadata) => {
+                let root = self.root.as_ref().map_or(path.as_path(), |(root, _)| root);
+                with_parent_ignores(root, &path, self.options, |ignores| {
+                    let ignores = if link.ignored { None } else { ignores };
+                    self.dir(path.clone(), ignores, link.depth, None, false, false)
+                })
+            }
+            Ok(_) => {
+                debug!("{} points to an already scanned directory", path.display());
+                Dir::new(0, path, None, EntryKind::Symlink)
+            }
+            Err(err) => self.unreadable(path, EntryKind::Symlink, err),
+        };
+        followed.kind = EntryKind::Symlink;
+        followed.target = fs::read_link(&link.path).ok();
+        if let Ok(metadata) = fs::symlink_metadata(&link.path) {
+            followed.set_metadata(&metadata);
+        }
+        if link.ignored {
+            followed.mark_ignored();
+        }
+        followed
+    }
+
+    /// Queues the subdirectories of a directory that was just listed to be added to the live tree
+    /// as pending
+    fn publish_listing(&self, path_to_dir: &Path, sub_dirs: Vec<PathBuf>) {
+        if let Some(live) = &self.live {
+            live.push(LiveUpdate::Listing(path_to_dir.to_path_buf(), sub_dirs));
+        }
+    }
+
+    /// Queues a scanned directory to be added to the live tree
+    ///
+    /// Returns the directory without its contents, as they are kept in the live tree, or unchanged
+    /// if there is no live tree
+    fn publish(&self, mut dir: Dir) -> Dir {
+        let live = match &self.live {
+            Some(live) => live,
+            None => return dir,
+        };
+        let contents = dir.contents.take();
+        let summary = dir.clone();
+        dir.contents = contents;
+        live.push(LiveUpdate::Scanned(Box::new(dir)));
+        summary
+    }
+
+    /// Checks if a directory is on another filesystem than the root when staying on one filesystem
+    fn crosses_mount(&self, metadata: &fs::Metadata) -> bool {
+        self.root_device.is_some() && device_id(metadata) != self.root_device
+    }
+
+    /// Makes a leaf node for a mount point that is not scanned
+    fn mount_point(&self, path: PathBuf) -> Dir {
+        debug!("{} is on another filesystem, skipping", path.display());
+        Dir::new(0, path, None, EntryKind::MountPoint)
+    }
+
+    /// Id of a file that can be reached through several paths and has to be counted once, every
+    /// file can when following symlinks
+    fn linked_id(&self, metadata: &fs::Metadata) -> Option<(u64, u64)> {
+        if link_count(metadata) <= 1 && !self.following() {
+            return None;
+        }
+        file_id(metadata)
+    }
+
+    /// Records a file in the set of seen inodes, returns false if it was already counted
+    fn first_link(&self, id: (u64, u64)) -> bool {
+        self.state.seen_inodes.lock().unwrap().insert(id)
+    }
+
+    /// Records a directory in the set of scanned directories, returns false if it was already scanned
+    fn first_dir(&self, metadata: &fs::Metadata) -> bool {
+        match file_id(metadata) {
+            Some(id) => self.state.seen_dirs.lock().unwrap().insert(id),
+            None => true,
+        }
+    }
+
+    /// Scans a single directory and all of its subdirectories
+    ///
+    /// `ignores` are the ignore rules of the parent directories, if ignored entries are detected,
+    /// `depth` is the depth of the directory below the root, `cached` is the directory in the
+    /// cached tree and `unchanged` is set if it didn't change since, so it isn't listed again.
+    /// `live` is set if the directory has a place in the live tree, directories reached through
+    /// symlinks don't.
+    fn dir(
+        &self,
+        path_to_dir: PathBuf,
+        ignores: Option<&IgnoreStack>,
+        depth: usize,
+        cached: Option<&Dir>,
+        unchanged: bool,
+        live: bool,
+    ) -> Dir {
+        // files that were too new for the cached scan are missing from its listing
+        let reuse_listing = unchanged && self.options.modified_before.is_none();
+        let listing = match cached {
+            Some(cached) if reuse_listing => self.cached_listing(cached),
+            _ => match self.list(&path_to_dir) {
+                Ok(listing) => listing,
+                Err(err) => return self.unreadable(path_to_dir, EntryKind::Directory, err),
+            },
+        };
+        let Listing {
+            entries,
+            excluded,
+            sub_dirs,
+            error: listing_error,
+        } = listing;
+        self.state.dirs.fetch_add(1, Ordering::Relaxed);
+        self.report_progress(&path_to_dir);
+        if live {
+            self.publish_listing(&path_to_dir, sub_dirs);
+        }
+        let pushed = ignores.and_then(|ignores| {
+            let names: Vec<&OsStr> = entries.iter().filter_map(|path| path.file_name()).collect();
+            ignores.push(&path_to_dir, &names)
+        });
+        let ignores = pushed.as_ref().or(ignores);
+
+        let cached_entries: HashMap<&OsStr, &Dir> = cached
+            .and_then(|cached| cached.contents.as_ref())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| Some((entry.path.file_name()?, entry)))
+            .collect();
+        let scan_entry = |path: PathBuf| {
+            let cached = path
+                .file_name()
+                .and_then(|name| cached_entries.get(name).copied());
+            match cached {
+                // the files of unchanged directories are taken from the cache as they are
+                Some(cached) if unchanged && reusable(cached) => self.reuse(cached),
+                _ => self.entry(path, ignores, depth + 1, cached, live),
+            }
+        };
+        let contents = if self.parallel {
+            entries.into_par_iter().filter_map(scan_entry).collect()
+        } else {
+            entries.into_iter().filter_map(scan_entry).collect()
+        };
+
+        let mut dir = Dir::from_contents(path_to_dir, contents);
+        // the entries that were listed before the error are kept
+        if let Some(err) = listing_error {
+            self.state.errors.fetch_add(1, Ordering::Relaxed);
+            log_error(&dir.path, &err);
+            dir.error = Some(format!("not all entries could be listed: {}", err));
+            dir.recalculate();
+        }
+        // entries that were skipped once the scan was cancelled are missing from the contents
+        dir.incomplete = self.cancelled();
+        dir.excluded_count = excluded.len() as u64;
+        if self.options.summarize_excluded {
+            for excluded in excluded.into_iter().filter_map(|path| self.excluded(path)) {
+                dir.excluded_size += excluded.size;
+                dir.excluded_disk_size += excluded.disk_size;
+            }
+        }
+        // reused listings still have the excluded entries of the cached scan
+        if let Some(cached) = cached.filter(|_| reuse_listing) {
+            dir.excluded_count += cached.excluded_count;
+            dir.excluded_size += cached.excluded_size;
+            dir.excluded_disk_size += cached.excluded_disk_size;
+        }
+        // the contents of every level below the maximum depth are dropped as soon as they are
+        // summed up, so only the directories that are currently being scanned are kept in memory
+        if self
+            .options
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+        {
+            dir.collapse();
+        }
+        dir
+    }
+
+    /// Lists the entries of a directory
+    fn list(&self, path_to_dir: &Path) -> io::Result<Listing> {
+        let r_dir = fs::read_dir(path_to_dir)?;
+
+        // Collecting the entries first, an indexed iterator splits much better than `par_bridge`,
+        // the file type comes from the directory listing so excluded entries are never stat'ed
+        let mut listing = Listing::default();
+        for entry in r_dir {
+            if self.cancelled() {
+                break;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    listing.error = Some(err);
+                    continue;
+                }
+            };
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let path = entry.path();
+            if self.options.filter.is_excluded(&path, is_dir) {
+                debug!("{} is excluded", path.display());
+                listing.excluded.push(path);
+            } else {
+                if is_dir && self.live.is_some() {
+                    listing.sub_dirs.push(path.clone());
+                }
+                listing.entries.push(path);
+            }
+        }
+        // in name order, so the contents of the tree don't depend on the order of the filesystem
+        listing.entries.sort_unstable();
+        Ok(listing)
+    }
+
+    /// Lists the entries of an unchanged directory from the cached tree
+    fn cached_listing(&self, cached: &Dir) -> Listing {
+        debug!(
+            "{} didn't change, reusing its listing",
+            cached.path.display()
+        );
+        let mut listing = Listing::default();
+        for entry in cached.contents.iter().flatten() {
+            let is_dir = entry.is_dir();
+            if self.options.filter.is_excluded(&entry.path, is_dir) {
+                listing.excluded.push(entry.path.clone());
+                continue;
+            }
+            if is_dir && self.live.is_some() {
+                listing.sub_dirs.push(entry.path.clone());
+            }
+            listing.entries.push(entry.path.clone());
+        }
+        listing
+    }
+
+    /// Takes an entry of an unchanged directory from the cached tree, returns None if it was
+    /// modified too recently
+    fn reuse(&self, cached: &Dir) -> Option<Dir> {
+        if cached.kind != EntryKind::Symlink && self.too_new(cached.modified) {
+            debug!(
+                "{} was modified too recently, skipping",
+                cached.path.display()
             );
-            return Dir::new(0, path_to_dir, None, false);
+            return None;
         }
-    };
+        let mut reused = cached.clone();
+        // counted where it is reached first until the tree is complete, like a scanned file
+        reused.hard_link = reused.inode.is_some_and(|id| !self.first_link(id));
+        self.state.files.fetch_add(1, Ordering::Relaxed);
+        self.state.bytes.fetch_add(cached.size, Ordering::Relaxed);
+        Some(reused)
+    }
 
-    for entry in r_dir {
-        let entry = entry.unwrap();
-        let path = entry.path();
+    /// Scans an excluded entry without the filter to summarize its size on the parent
+    fn excluded(&self, path: PathBuf) -> Option<Dir> {
+        let options = ScanOptions {
+            filter: PathFilter::default(),
+            progress: None,
+            ..self.options.clone()
+        };
+        let mut scan = Scan::new(&options, false);
+        scan.root_device = self.root_device;
+        scan.root = self.root.clone();
+        let mut entry = scan.entry(path, None, 0, None, false)?;
+        scan.finish(&mut entry);
+        Some(entry)
+    }
+
+    /// Scans a single entry of a directory, recursing into it if it's a directory
+    ///
+    /// `cached` is the entry in the cached tree, if there is one, `live` is set if the directory of
+    /// the entry has a place in the live tree. Returns None if the scan was cancelled
+    fn entry(
+        &self,
+        path: PathBuf,
+        ignores: Option<&IgnoreStack>,
+        depth: usize,
+        cached: Option<&Dir>,
+        live: bool,
+    ) -> Option<Dir> {
+        if self.cancelled() {
+            return None;
+        }
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            // entries removed since their directory was listed are gone, not unreadable
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                debug!("{} was removed while scanning, skipping", path.display());
+                return None;
+            }
+            Err(err) => return Some(self.unreadable(path, EntryKind::Other, err)),
+        };
+        let file_type = metadata.file_type();
+        if !file_type.is_dir() && !file_type.is_symlink() && self.too_new(metadata.modified().ok())
+        {
+            debug!("{} was modified too recently, skipping", path.display());
+            return None;
+        }
+
+        // everything below an ignored directory is ignored, no need to match it any further
+        let ignored = ignores.is_some_and(|ignores| ignores.is_ignored(&path, file_type.is_dir()));
+        let ignores = if ignored { None } else { ignores };
 
-        if path.is_dir() {
+        let mut entry = if file_type.is_dir() {
             debug!("{} is a directory", path.display());
-            contents.push(make_dir_tree(path));
+            if self.crosses_mount(&metadata) {
+                return Some(self.mount_point(path));
+            }
+            if self.following() && !self.first_dir(&metadata) {
+                // reached through a symlink to a directory it is in, after a symlink to the
+                // directory itself, its contents are counted there
+                debug!("{} was already scanned through a symlink", path.display());
+                let mut dir = Dir::new(0, path, None, EntryKind::Directory);
+                dir.hard_link = true;
+                dir
+            } else {
+                let unchanged = cached.is_some_and(|cached| unchanged(cached, &metadata));
+                self.dir(path, ignores, depth, cached, unchanged, live)
+            }
+        } else if file_type.is_symlink() {
+            debug!("{} is a symlink", path.display());
+            self.symlink(path, &metadata, ignored, depth)
         } else {
-            let size = match fs::metadata(&path) {
-                Ok(file) => file.len(),
-                Err(err) => match err.kind() {
-                    ErrorKind::PermissionDenied => {
-                        warn!(
-                            "Permission denied when accesing file/directory: {}",
-                            path.display()
-                        );
-                        continue;
-                    }
-                    _ => {
-                        warn!(
-                            "Error occured when trying to read {} error: {}",
-                            path.display(),
-                            err
-                        );
-                        continue;
-                    }
-                },
+            let kind = if file_type.is_file() {
+                EntryKind::File
+            } else {
+                EntryKind::Other
             };
-            debug!("{} is a file with size: {} bytes", path.display(), size);
-            let is_file = path.is_file();
-            contents.push(Dir::new(size, path, None, is_file));
+            self.file(path, &metadata, kind)
+        };
+        entry.set_metadata(&metadata);
+        if ignored {
+            entry.mark_ignored();
+        }
+        if live && file_type.is_dir() {
+            entry = self.publish(entry);
+        }
+        Some(entry)
+    }
+
+    /// Makes a symlink node, following the link to its target if the policy allows it
+    ///
+    /// Links to directories within the tree are not followed, the directories are counted where
+    /// they are, links to directories outside of it are pending until the tree is scanned.
+    fn symlink(
+        &self,
+        path: PathBuf,
+        link_metadata: &fs::Metadata,
+        ignored: bool,
+        depth: usize,
+    ) -> Dir {
+        let target = fs::read_link(&path).ok();
+        let resolved = match self.options.symlinks {
+            SymlinkPolicy::Never => None,
+            SymlinkPolicy::Follow => fs::metadata(&path).ok(),
+        };
+        let mut link = match resolved {
+            Some(metadata) if metadata.is_dir() && self.crosses_mount(&metadata) => {
+                self.mount_point(path)
+            }
+            Some(metadata) if metadata.is_dir() && self.within_tree(&path) => {
+                debug!("{} points within the scanned tree", path.display());
+                Dir::new(0, path, None, EntryKind::Symlink)
+            }
+            Some(metadata) if metadata.is_dir() => {
+                self.state.links.lock().unwrap().push(LinkToFollow {
+                    path: path.clone(),
+                    depth,
+                    ignored,
+                });
+                let mut link = Dir::new(0, path, None, EntryKind::Symlink);
+                link.pending = true;
+                link
+            }
+            Some(metadata) => self.file(path, &metadata, EntryKind::Symlink),
+            // the link itself when not following or when the target doesn't exist
+            None => self.file(path, link_metadata, EntryKind::Symlink),
+        };
+        link.kind = EntryKind::Symlink;
+        link.target = target;
+        link
+    }
+
+    /// Makes a leaf Dir out of a non directory entry
+    fn file(&self, path: PathBuf, metadata: &fs::Metadata, kind: EntryKind) -> Dir {
+        let size = metadata.len();
+        debug!("{} is a file with size: {} bytes", path.display(), size);
+        let mut file = Dir::new(size, path, None, kind);
+        file.disk_size = allocated_size(metadata);
+        file.inode = self.linked_id(metadata);
+        // counted where it is reached first until the tree is complete, then where it comes first
+        // in the tree
+        file.hard_link = file.inode.is_some_and(|id| !self.first_link(id));
+        self.state.files.fetch_add(1, Ordering::Relaxed);
+        self.state.bytes.fetch_add(size, Ordering::Relaxed);
+        file
+    }
+
+    /// Calls the progress callback with the current counters
+    fn report_progress(&self, current_path: &Path) {
+        if let Some(progress) = &self.options.progress {
+            (progress.0)(&ScanProgress {
+                files: self.state.files.load(Ordering::Relaxed),
+                dirs: self.state.dirs.load(Ordering::Relaxed),
+                bytes: self.state.bytes.load(Ordering::Relaxed),
+                errors: self.state.errors.load(Ordering::Relaxed),
+                current_path: current_path.to_path_buf(),
+            });
         }
     }
-    let sizes: Vec<u64> = contents.iter().map(|x: &Dir| x.size).collect();
-    let dir = Dir::new(sizes.iter().sum(), path_to_dir, Some(contents), false);
-    dir
+
+    /// Makes an empty node for an entry that could not be read, logging and counting the error
+    fn unreadable(&self, path: PathBuf, kind: EntryKind, err: io::Error) -> Dir {
+        self.state.errors.fetch_add(1, Ordering::Relaxed);
+        log_error(&path, &err);
+        let mut dir = Dir::new(0, path, None, kind);
+        dir.error = Some(err.to_string());
+        dir.recalculate();
+        dir
+    }
 }
 
-pub fn make_dir_tree_parallel(path_to_dir: PathBuf) -> Dir {
-    let r_dir = match fs::read_dir(&path_to_dir) {
-        Ok(dir) => dir,
-        Err(err) => {
+/// Logs an error that occured when reading an entry
+fn log_error(path: &Path, err: &io::Error) {
+    match err.kind() {
+        ErrorKind::PermissionDenied => {
+            warn!(
+                "Permission denied when accesing file/directory: {}",
+                path.display()
+            );
+        }
+        _ => {
             warn!(
                 "Error occured when trying to read {} error: {}",
-                path_to_dir.display(),
+                path.display(),
                 err
             );
-            return Dir::new(0, path_to_dir, None, false);
         }
-    };
-    let contents = r_dir
-        .into_iter()
-        .par_bridge()
-        .filter_map(|entry| {
-            let entry = entry.unwrap();
-            let path = entry.path();
-
-            if path.is_dir() {
-                debug!("{} is a directory", path.display());
-                Some(make_dir_tree(path))
-            } else {
-                let size = match fs::metadata(&path) {
-                    Ok(file) => file.len(),
-                    Err(err) => match err.kind() {
-                        ErrorKind::PermissionDenied => {
-                            warn!(
-                                "Permission denied when accesing file/directory: {}",
-                                path.display()
-                            );
-                            return None;
-                        }
-                        _ => {
-                            warn!(
-                                "Error occured when trying to read {} error: {}",
-                                path.display(),
-                                err
-                            );
-                            return None;
-                        }
-                    },
-                };
-                debug!("{} is a file with size: {} bytes", path.display(), size);
-                let is_file = path.is_file();
-                Some(Dir::new(size, path, None, is_file))
-            }
-        })
-        .collect::<Vec<Dir>>();
-
-    let sizes: Vec<u64> = contents.iter().map(|x: &Dir| x.size).collect();
-    let dir = Dir::new(sizes.iter().sum(), path_to_dir, Some(contents), false);
-    dir
+    }
 }
 
-fn _benchmark_make_dir_tree(func: fn(PathBuf) -> Dir, n: i32) -> f32 {
+fn _benchmark_make_dir_tree(func: fn(PathBuf) -> Dir, root: &Path, n: i32) -> f32 {
     let mut times: Vec<Duration> = vec![];
     for _i in 0..n {
         let start = Instant::now();
-        let root = Path::new(r".");
         let _result = func(root.to_path_buf());
         let end = Instant::now();
         let time = end - start;
@@ -154,27 +1201,97 @@ fn _benchmark_make_dir_tree(func: fn(PathBuf) -> Dir, n: i32) -> f32 {
     average_time
 }
 
+/// Creates a skewed directory tree inside of a temporary directory
+///
+/// The root only holds a single small file and one big subdirectory containing `width` nested
+/// directories with `files` files of 100 bytes each, the shape that a first level only parallel
+/// scan can't split
+#[cfg(test)]
+fn make_skewed_tree(width: usize, files: usize) -> tempfile::TempDir {
+    let root = tempfile::tempdir().unwrap();
+    fs::write(root.path().join("small.txt"), b"small").unwrap();
+    for i in 0..width {
+        let sub_dir = root
+            .path()
+            .join("big")
+            .join(format!("dir_{}", i))
+            .join("nested");
+        fs::create_dir_all(&sub_dir).unwrap();
+        for j in 0..files {
+            fs::write(sub_dir.join(format!("file_{}", j)), vec![0u8; 100]).unwrap();
+        }
+    }
+    root
+}
+
+/// Apparent size of a directory and every directory below it, without their files
+#[cfg(test)]
+pub(crate) fn dirs_size(path: &Path) -> u64 {
+    let mut size = fs::metadata(path).unwrap().len();
+    for entry in fs::read_dir(path).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            size += dirs_size(&entry.path());
+        }
+    }
+    size
+}
+
 #[cfg(test)]
 mod bench {
-    use crate::scanning::{_benchmark_make_dir_tree, make_dir_tree, make_dir_tree_parallel};
+    use crate::scanning::{
+        _benchmark_make_dir_tree, make_dir_tree, make_dir_tree_parallel, make_skewed_tree,
+    };
+    use std::path::Path;
 
     #[test]
     #[ignore]
     fn benchmark_make_dir_tree() {
-        _benchmark_make_dir_tree(make_dir_tree, 10);
+        _benchmark_make_dir_tree(make_dir_tree, Path::new(r"."), 10);
     }
 
     #[test]
     #[ignore]
     fn benchmark_make_dir_tree_parallel() {
-        _benchmark_make_dir_tree(make_dir_tree_parallel, 10);
+        _benchmark_make_dir_tree(make_dir_tree_parallel, Path::new(r"."), 10);
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_skewed_tree() {
+        let root = make_skewed_tree(64, 500);
+        let sequential = _benchmark_make_dir_tree(make_dir_tree, root.path(), 10);
+        let parallel = _benchmark_make_dir_tree(make_dir_tree_parallel, root.path(), 10);
+        println!("speedup on skewed tree: {:.2}x", sequential / parallel);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::scanning::{make_dir_tree, make_dir_tree_parallel};
-    use std::path::Path;
+    use crate::filter::PathFilter;
+    use crate::scanning::{
+        device_id, dirs_size, make_dir_tree, make_dir_tree_live, make_dir_tree_parallel,
+        make_dir_tree_parallel_with, make_dir_tree_with, make_dir_tree_within, make_skewed_tree,
+        scan_entry, CancelToken, ProgressCallback, Scan, ScanCache, ScanOptions, ScanProgress,
+        ScanReport, SymlinkPolicy,
+    };
+    use crate::structs::{Dir, EntryKind, SizeMode};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, SystemTime};
+
+    /// Creates a temporary directory holding files of the given sizes, along with their parents
+    fn make_tree(files: &[(&str, usize)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (path, size) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; *size]).unwrap();
+        }
+        root
+    }
 
     #[test]
     fn test_make_dir_tree() {
@@ -193,4 +1310,821 @@ mod test {
             println!("{}", sub_dir.display_default())
         }
     }
+
+    #[test]
+    fn test_parallel_matches_sequential() {
+        fn flatten(dir: &Dir, out: &mut Vec<(String, u64)>) {
+            out.push((dir.path.display().to_string(), dir.size));
+            for sub_dir in dir.contents.iter().flatten() {
+                flatten(sub_dir, out);
+            }
+        }
+        let root = make_skewed_tree(8, 20);
+        let (mut sequential, mut parallel) = (vec![], vec![]);
+        flatten(&make_dir_tree(root.path().to_path_buf()), &mut sequential);
+        flatten(
+            &make_dir_tree_parallel(root.path().to_path_buf()),
+            &mut parallel,
+        );
+        sequential.sort();
+        parallel.sort();
+
+        assert_eq!(sequential, parallel);
+        assert_eq!(
+            parallel.iter().find(|(p, _)| p.ends_with("big")).unwrap().1,
+            8 * 20 * 100 + dirs_size(&root.path().join("big"))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parallel_hard_links() {
+        fn flatten(dir: &Dir, out: &mut Vec<(PathBuf, u64, bool)>) {
+            out.push((dir.path.clone(), dir.size, dir.hard_link));
+            for sub_dir in dir.contents.iter().flatten().chain(&dir.hidden) {
+                flatten(sub_dir, out);
+            }
+        }
+        let root = make_skewed_tree(8, 20);
+        let big = root.path().join("big/dir_7/nested/file_19");
+        fs::create_dir(root.path().join("h")).unwrap();
+        // a parallel scan gets to the link in the small directory first
+        fs::hard_link(&big, root.path().join("h/link")).unwrap();
+        for max_depth in [None, Some(1)] {
+            let options = ScanOptions {
+                max_depth,
+                ..Default::default()
+            };
+            let (mut sequential, mut parallel) = (vec![], vec![]);
+            let tree = make_dir_tree_with(root.path().to_path_buf(), &options);
+            flatten(&tree, &mut sequential);
+            for _ in 0..10 {
+                parallel.clear();
+                let tree = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+                flatten(&tree, &mut parallel);
+                assert_eq!(sequential, parallel);
+            }
+            let link = sequential
+                .iter()
+                .find(|(path, ..)| path.ends_with("h/link"));
+            assert!(link.unwrap().2);
+            let h = sequential.iter().find(|(path, ..)| path.ends_with("h"));
+            assert_eq!(h.unwrap().1, dirs_size(&root.path().join("h")));
+        }
+    }
+
+    #[test]
+    fn test_counts() {
+        let root = make_tree(&[
+            ("readme.md", 10),
+            ("src/main.rs", 20),
+            ("src/lib.rs", 30),
+            ("src/bin/tool.rs", 40),
+            ("docs/guide.md", 50),
+        ]);
+        let tree = make_dir_tree_parallel(root.path().to_path_buf());
+
+        // src, src/bin and docs
+        assert_eq!((tree.file_count, tree.dir_count), (5, 3));
+        assert_eq!(tree.entry_count(), 8);
+        let src = tree.get(&root.path().join("src")).unwrap();
+        assert_eq!((src.file_count, src.dir_count), (3, 1));
+        let file = tree.get(&root.path().join("readme.md")).unwrap();
+        assert_eq!((file.file_count, file.dir_count), (1, 0));
+
+        // collapsed directories keep their counts
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let collapsed = make_dir_tree_with(root.path().to_path_buf(), &options);
+        assert!(collapsed.get(&root.path().join("src/bin")).is_none());
+        assert_eq!(collapsed.entry_count(), 8);
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let root = make_tree(&[("old/a.txt", 3), ("old/b.txt", 4), ("recent/c.txt", 5)]);
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        let set_modified = |path: &Path, age: Duration| {
+            let file = fs::File::open(path).unwrap();
+            file.set_modified(now - age).unwrap();
+        };
+        // every file is at least a year old except one
+        for entry in walk(root.path()) {
+            if entry.is_file() {
+                set_modified(&entry, 400 * day);
+            }
+        }
+        let new_file = root.path().join("recent/c.txt");
+        set_modified(&new_file, day);
+        for entry in walk(root.path()).into_iter().rev() {
+            if entry.is_dir() {
+                set_modified(&entry, 500 * day);
+            }
+        }
+        let tree = make_dir_tree(root.path().to_path_buf());
+
+        assert_eq!(tree.modified, Some(now - 500 * day));
+        assert_eq!(tree.newest_modified, Some(now - day));
+        let old = tree.get(&root.path().join("old")).unwrap();
+        assert_eq!(old.newest_modified, Some(now - 400 * day));
+        assert!(tree.get(&new_file).unwrap().accessed.is_some());
+
+        // only the stale files are counted
+        let options = ScanOptions {
+            modified_before: Some(now - 180 * day),
+            ..Default::default()
+        };
+        let stale = make_dir_tree_with(root.path().to_path_buf(), &options);
+        assert_eq!(stale.size, 3 + 4 + dirs_size(root.path()));
+        assert!(stale.get(&new_file).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_owners() {
+        use std::os::unix::fs::MetadataExt;
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("sub")).unwrap();
+        fs::write(root.path().join("sub/file"), "abc").unwrap();
+        let tree = make_dir_tree(root.path().to_path_buf());
+
+        for path in [root.path().join("sub"), root.path().join("sub/file")] {
+            let metadata = fs::symlink_metadata(&path).unwrap();
+            let scanned = tree.get(&path).unwrap();
+            assert_eq!(scanned.uid, Some(metadata.uid()));
+            assert_eq!(scanned.gid, Some(metadata.gid()));
+        }
+    }
+
+    /// Lists every entry below a directory, parents before their contents
+    fn walk(path: &Path) -> Vec<PathBuf> {
+        let mut entries = vec![path.to_path_buf()];
+        for entry in fs::read_dir(path).unwrap() {
+            let entry = entry.unwrap().path();
+            if entry.is_dir() {
+                entries.extend(walk(&entry));
+            } else {
+                entries.push(entry);
+            }
+        }
+        entries
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_sparse_file_sizes() {
+        let root = tempfile::tempdir().unwrap();
+        let sparse = fs::File::create(root.path().join("sparse.img")).unwrap();
+        sparse.set_len(100_000_000).unwrap();
+        let tree = make_dir_tree_parallel(root.path().to_path_buf());
+
+        assert_eq!(
+            tree.size_in(SizeMode::Apparent),
+            100_000_000 + dirs_size(root.path())
+        );
+        assert!(tree.size_in(SizeMode::Disk) < 1_000_000);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_directory_sizes() {
+        use std::os::unix::fs::MetadataExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let (sub, empty) = (root.path().join("sub"), root.path().join("empty"));
+        fs::create_dir(&sub).unwrap();
+        fs::create_dir(&empty).unwrap();
+        fs::write(root.path().join("a.txt"), vec![0u8; 100]).unwrap();
+        fs::write(sub.join("b.txt"), vec![0u8; 50]).unwrap();
+        let tree = make_dir_tree_parallel(root.path().to_path_buf());
+
+        // every entry counts like in `du`, the directories themselves included
+        let entries = [
+            root.path(),
+            &sub,
+            &empty,
+            &root.path().join("a.txt"),
+            &sub.join("b.txt"),
+        ];
+        let metadata = entries.map(|entry| fs::symlink_metadata(entry).unwrap());
+        let apparent: u64 = metadata.iter().map(|metadata| metadata.len()).sum();
+        let disk: u64 = metadata
+            .iter()
+            .map(|metadata| metadata.blocks() * 512)
+            .sum();
+        assert_eq!(tree.size_in(SizeMode::Apparent), apparent);
+        assert_eq!(tree.size_in(SizeMode::Disk), disk);
+        assert!(apparent > 150);
+
+        let empty_dir = tree.get(&empty).unwrap();
+        assert_eq!(empty_dir.size, fs::metadata(&empty).unwrap().len());
+        assert_eq!(empty_dir.own_size, empty_dir.size);
+        let sub_dir = tree.get(&sub).unwrap();
+        assert_eq!(sub_dir.size, sub_dir.own_size + 50);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_hard_links_counted_once() {
+        let root = tempfile::tempdir().unwrap();
+        let original = root.path().join("original");
+        fs::write(&original, vec![0u8; 1000]).unwrap();
+        for sub_dir in ["a", "b"] {
+            fs::create_dir(root.path().join(sub_dir)).unwrap();
+            fs::hard_link(&original, root.path().join(sub_dir).join("link")).unwrap();
+        }
+        let tree = make_dir_tree_parallel(root.path().to_path_buf());
+
+        assert_eq!(tree.size, 1000 + dirs_size(root.path()));
+        let links = ["original", "a/link", "b/link"]
+            .map(|link| tree.get(&root.path().join(link)).unwrap().hard_link);
+        assert_eq!(links.iter().filter(|&&duplicate| !duplicate).count(), 1);
+        assert_eq!(links.iter().filter(|&&duplicate| duplicate).count(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlink_policy() {
+        use std::os::unix::fs::symlink;
+
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("file"), vec![0u8; 1000]).unwrap();
+        symlink(&data, root.path().join("data_link")).unwrap();
+        symlink(root.path(), data.join("loop")).unwrap();
+
+        let never = make_dir_tree_parallel(root.path().to_path_buf());
+        let link = never.get(&root.path().join("data_link")).unwrap();
+        assert_eq!(link.kind, EntryKind::Symlink);
+        assert_eq!(link.target.as_deref(), Some(data.as_path()));
+        assert!(link.contents.is_none());
+        assert!(never.size < 2000 + dirs_size(root.path()));
+
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let follow = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+        assert_eq!(follow.size, 1000 + dirs_size(root.path()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_before_target() {
+        use std::os::unix::fs::symlink;
+
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("file"), vec![0u8; 1000]).unwrap();
+        // scanned before the directory it points to
+        symlink(&data, root.path().join("a_link")).unwrap();
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        let tree = make_dir_tree_with(root.path().to_path_buf(), &options);
+
+        assert_eq!(tree.size, 1000 + dirs_size(root.path()));
+        assert_eq!(tree.file_count, 2);
+        let link = tree.get(&root.path().join("a_link")).unwrap();
+        assert!(link.contents.is_none());
+        assert_eq!(link.size, 0);
+        assert!(!tree.get(&data).unwrap().hard_link);
+        assert_eq!(tree.get(&data).unwrap().size, 1000 + dirs_size(&data));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_outside_of_tree() {
+        use std::os::unix::fs::symlink;
+
+        let outside = make_tree(&[("sub/file", 1000)]);
+        let root = make_tree(&[("a/file", 10)]);
+        // the link with the smallest path is followed, the one below it points into what it counts
+        symlink(outside.path().join("sub"), root.path().join("a/link")).unwrap();
+        symlink(outside.path(), root.path().join("b_link")).unwrap();
+        let options = ScanOptions {
+            symlinks: SymlinkPolicy::Follow,
+            ..Default::default()
+        };
+        for parallel in [false, true] {
+            let tree = match parallel {
+                true => make_dir_tree_parallel_with(root.path().to_path_buf(), &options),
+                false => make_dir_tree_with(root.path().to_path_buf(), &options),
+            };
+            let first = tree.get(&root.path().join("a/link")).unwrap();
+            assert!(!first.pending);
+            assert_eq!(first.kind, EntryKind::Symlink);
+            // the directory a link points to is counted without its own size, like the link
+            assert_eq!(first.size, 1000);
+            let second = tree.get(&root.path().join("b_link")).unwrap();
+            let counted = second.get(&root.path().join("b_link/sub")).unwrap();
+            assert!(counted.hard_link);
+            assert!(counted.contents.is_none());
+            assert_eq!(second.size, 0);
+        }
+    }
+
+    #[test]
+    fn test_one_file_system() {
+        let root = tempfile::tempdir().unwrap();
+        let data = root.path().join("data");
+        fs::create_dir(&data).unwrap();
+        fs::write(data.join("file"), "abc").unwrap();
+        let options = ScanOptions {
+            one_file_system: true,
+            ..Default::default()
+        };
+        // everything is on the filesystem of the root
+        let tree = make_dir_tree_with(root.path().to_path_buf(), &options);
+        assert_eq!(tree.size, 3 + dirs_size(root.path()));
+        assert_eq!(tree.get(&data).unwrap().kind, EntryKind::Directory);
+
+        // directories on any other device are mount points, they are not scanned
+        let device = device_id(&fs::metadata(root.path()).unwrap());
+        let mut scan = Scan::new(&options, false);
+        scan.root_device = Some(device.map_or(1, |device| device + 1));
+        let mount_point = scan.entry(data.clone(), None, 1, None, false).unwrap();
+        assert_eq!(mount_point.kind, EntryKind::MountPoint);
+        assert_eq!(mount_point.size, 0);
+        assert!(mount_point.contents.is_none());
+
+        #[cfg(target_os = "linux")]
+        {
+            // followed links to other filesystems are not scanned either, /proc is always a
+            // filesystem of its own
+            std::os::unix::fs::symlink("/proc", root.path().join("proc")).unwrap();
+            let options = ScanOptions {
+                symlinks: SymlinkPolicy::Follow,
+                ..options
+            };
+            let tree = make_dir_tree_with(root.path().to_path_buf(), &options);
+            assert_eq!(tree.size, 3 + dirs_size(root.path()));
+            let link = tree.get(&root.path().join("proc")).unwrap();
+            assert!(link.contents.is_none());
+        }
+    }
+
+    #[test]
+    fn test_exclude_patterns() {
+        let root = make_tree(&[("notes.txt", 5), ("disk.iso", 1000), ("build/out.bin", 200)]);
+        let options = ScanOptions {
+            filter: PathFilter::new(&["*.iso".to_string(), "build".to_string()], &[]).unwrap(),
+            summarize_excluded: true,
+            ..Default::default()
+        };
+        let tree = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+
+        let root_size = fs::metadata(root.path()).unwrap().len();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.size, 5 + root_size);
+        assert_eq!(tree.excluded_count, 2);
+        assert_eq!(
+            tree.excluded_size,
+            1000 + 200 + dirs_size(&root.path().join("build"))
+        );
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let root = tempfile::tempdir().unwrap();
+        let src = root.path().join("src");
+        fs::create_dir_all(root.path().join("target/debug")).unwrap();
+        fs::create_dir(&src).unwrap();
+        fs::write(root.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.path().join("target/debug/app"), vec![0u8; 100]).unwrap();
+        fs::write(root.path().join("build.log"), vec![0u8; 10]).unwrap();
+        fs::write(src.join(".ignore"), "generated.rs\n").unwrap();
+        fs::write(src.join("generated.rs"), vec![0u8; 7]).unwrap();
+        fs::write(src.join("main.rs"), vec![0u8; 5]).unwrap();
+        let options = ScanOptions {
+            gitignore: true,
+            ..Default::default()
+        };
+        let tree = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+
+        assert_eq!(
+            tree.ignored_size,
+            117 + dirs_size(&root.path().join("target"))
+        );
+        assert!(
+            tree.get(&root.path().join("target/debug/app"))
+                .unwrap()
+                .ignored
+        );
+        assert!(tree.get(&src.join("generated.rs")).unwrap().ignored);
+        assert!(!tree.get(&src.join("main.rs")).unwrap().ignored);
+        assert!(!tree.get(&src).unwrap().ignored);
+    }
+
+    #[test]
+    fn test_rescan_with_parent_ignores() {
+        let root = tempfile::tempdir().unwrap();
+        let logs = root.path().join("app/logs");
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(root.path().join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.path().join("app/.ignore"), "*.tmp\n").unwrap();
+        fs::write(logs.join("run.log"), "abc").unwrap();
+        fs::write(logs.join("run.tmp"), "abcde").unwrap();
+        fs::write(logs.join("notes.txt"), "ab").unwrap();
+        let options = ScanOptions {
+            gitignore: true,
+            ..Default::default()
+        };
+
+        // the ignore files of the root and of app apply to logs
+        let logs_tree = make_dir_tree_within(root.path(), logs.clone(), &options);
+        assert_eq!(logs_tree.ignored_size, 3 + 5);
+        assert!(!logs_tree.get(&logs.join("notes.txt")).unwrap().ignored);
+        let entry = scan_entry(root.path(), logs.join("run.log"), &options).unwrap();
+        assert!(entry.ignored);
+        let entry = scan_entry(root.path(), logs.join("notes.txt"), &options).unwrap();
+        assert!(!entry.ignored);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_rescan_hard_links() {
+        let root = make_tree(&[("a/file", 1000), ("b/other", 10)]);
+        let path = root.path().to_path_buf();
+        fs::hard_link(path.join("a/file"), path.join("b/link")).unwrap();
+        let mut tree = make_dir_tree(path.clone());
+        let size = tree.size;
+
+        // the link in b is counted in a, outside of what is scanned again
+        assert!(tree.splice(make_dir_tree_within(
+            &path,
+            path.join("b"),
+            &ScanOptions::default()
+        )));
+        assert_eq!(tree.size, size);
+        assert!(tree.get(&path.join("b/link")).unwrap().hard_link);
+        let entry = scan_entry(&path, path.join("b/link"), &ScanOptions::default()).unwrap();
+        assert!(tree.insert(entry));
+        assert_eq!(tree.size, size);
+        // the link in b is counted once the one in a is gone
+        let removed = tree.remove(&path.join("a/file")).unwrap();
+        assert!(!removed.hard_link);
+        assert!(!tree.get(&path.join("b/link")).unwrap().hard_link);
+        assert_eq!(tree.size, size);
+    }
+
+    #[test]
+    fn test_progress() {
+        let root = make_tree(&[("a/file", 1), ("a/b/file", 2), ("c/file", 3)]);
+        let last = Arc::new(Mutex::new(ScanProgress::default()));
+        let reported = last.clone();
+        let options = ScanOptions {
+            progress: Some(ProgressCallback::new(move |progress| {
+                let mut last = reported.lock().unwrap();
+                if progress.dirs > last.dirs {
+                    *last = progress.clone();
+                }
+            })),
+            ..Default::default()
+        };
+        make_dir_tree_with(root.path().to_path_buf(), &options);
+        let last = last.lock().unwrap();
+
+        // the root, a, a/b and c, c is reported before its own file is counted
+        assert_eq!(last.dirs, 4);
+        assert_eq!(last.files, 2);
+        assert_eq!(last.bytes, 1 + 2);
+        assert_eq!(last.current_path, root.path().join("c"));
+        assert_eq!(last.errors, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_errors() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // SAFETY: geteuid can't fail and has no side effects
+        if unsafe { libc::geteuid() } == 0 {
+            eprintln!("skipping test_errors, root can read every directory");
+            return;
+        }
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("kept"), "abc").unwrap();
+        fs::create_dir(root.path().join("open")).unwrap();
+        fs::write(root.path().join("open/file"), "abcde").unwrap();
+        let locked = root.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("file"), "abcdefgh").unwrap();
+        let dirs = dirs_size(root.path());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let dir = make_dir_tree(root.path().to_path_buf());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // the locked directory itself is counted, its contents can't be
+        assert_eq!(dir.size, dirs + 3 + 5);
+        assert_eq!(dir.file_count, 2);
+        assert_eq!(dir.error_count, 1);
+        assert!(dir.get(&locked).unwrap().error.is_some());
+        let report = ScanReport::new(&dir);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].path, locked);
+        assert!(!report.incomplete);
+        assert!(ScanReport::new(&make_dir_tree(root.path().to_path_buf()))
+            .errors
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_errors_below_max_depth() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // SAFETY: geteuid can't fail and has no side effects
+        if unsafe { libc::geteuid() } == 0 {
+            eprintln!("skipping test_errors_below_max_depth, root can read every directory");
+            return;
+        }
+        let root = make_tree(&[("data/nested/locked/file", 8), ("data/file", 5)]);
+        let locked = root.path().join("data/nested/locked");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let mut dir = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let data = dir.get(&root.path().join("data")).unwrap();
+        assert!(data.collapsed);
+        assert_eq!(data.error_count, 1);
+        let report = ScanReport::new(&dir);
+        assert_eq!(report.errors.len() as u64, dir.error_count);
+        assert_eq!(report.errors[0].path, locked);
+        // the count stays the same when the collapsed directory is summed up again
+        dir.update(&root.path().join("data"), Dir::recalculate);
+        assert_eq!(dir.error_count, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_odd_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = make_tree(&[("sub/file", 5)]);
+        let odd_dir = root.path().join(OsStr::from_bytes(b"dir_\xff"));
+        fs::create_dir(&odd_dir).unwrap();
+        fs::write(
+            odd_dir.join(OsStr::from_bytes(b"file_\xfe")),
+            vec![0u8; 100],
+        )
+        .unwrap();
+        let tree = make_dir_tree_parallel(root.path().to_path_buf());
+
+        assert_eq!(tree.size, 5 + 100 + dirs_size(root.path()));
+        let odd = tree.get(&odd_dir).unwrap();
+        assert_eq!(odd.name(), "dir_\u{FFFD}");
+        assert_eq!(odd.contents.as_ref().unwrap()[0].name(), "file_\u{FFFD}");
+
+        // paths without a file name
+        let up = make_dir_tree(root.path().join("sub").join(".."));
+        assert_eq!(up.size, tree.size);
+        assert!(up.name().ends_with(".."));
+    }
+
+    #[test]
+    fn test_deleted_mid_scan() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("kept"), "abc").unwrap();
+        fs::write(root.path().join("removed_file"), "abcdefgh").unwrap();
+        fs::create_dir(root.path().join("removed_dir")).unwrap();
+        fs::write(root.path().join("removed_dir/file"), "abcdefgh").unwrap();
+        let root_path = root.path().to_path_buf();
+        let kept_dirs = dirs_size(root.path()) - dirs_size(&root.path().join("removed_dir"));
+        // removing entries after the root was listed, before they are read
+        let options = ScanOptions {
+            progress: Some(ProgressCallback::new(move |progress| {
+                if progress.current_path == root_path {
+                    let _ = fs::remove_file(root_path.join("removed_file"));
+                    let _ = fs::remove_dir_all(root_path.join("removed_dir"));
+                }
+            })),
+            ..Default::default()
+        };
+        let tree = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+
+        assert_eq!(tree.file_count, 1);
+        assert_eq!(tree.dir_count, 0);
+        assert_eq!(tree.size, kept_dirs + 3);
+        assert_eq!(tree.error_count, 0);
+        assert!(ScanReport::new(&tree).errors.is_empty());
+        assert_eq!(tree.contents.as_ref().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let root = make_tree(&[("a/file", 10), ("b/file", 10), ("c/file", 10)]);
+        let cancel = CancelToken::new();
+        let cancel_from_progress = cancel.clone();
+        // cancelled once b is listed: a is done, the file of b and all of c are skipped
+        let options = ScanOptions {
+            progress: Some(ProgressCallback::new(move |progress| {
+                if progress.dirs == 3 {
+                    cancel_from_progress.cancel();
+                }
+            })),
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let dir = make_dir_tree_with(root.path().to_path_buf(), &options);
+        let full = make_dir_tree(root.path().to_path_buf());
+
+        assert!(dir.incomplete);
+        assert!(!full.incomplete);
+        assert_eq!(dir.file_count, 1);
+        assert!(!dir.get(&root.path().join("a")).unwrap().incomplete);
+        let b = dir.get(&root.path().join("b")).unwrap();
+        assert!(b.incomplete);
+        assert_eq!(b.file_count, 0);
+        assert!(dir.get(&root.path().join("c")).is_none());
+    }
+
+    #[test]
+    fn test_live_tree() {
+        let root = make_tree(&[("kept/a", 10), ("kept/sub/b", 20), ("ignored/c", 30)]);
+        fs::write(root.path().join(".gitignore"), "ignored\n").unwrap();
+        let options = ScanOptions {
+            gitignore: true,
+            ..Default::default()
+        };
+        let live = Arc::new(Mutex::new(Dir::new(
+            0,
+            root.path().to_path_buf(),
+            None,
+            EntryKind::Directory,
+        )));
+        make_dir_tree_live(live.clone(), &options);
+        let live = live.lock().unwrap();
+        let scanned = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+
+        fn flatten(dir: &Dir, out: &mut Vec<(String, u64, u64, bool)>) {
+            out.push((
+                dir.path.display().to_string(),
+                dir.size,
+                dir.ignored_size,
+                dir.pending,
+            ));
+            for sub_dir in dir.contents.iter().flatten() {
+                flatten(sub_dir, out);
+            }
+        }
+        let (mut live_flat, mut scanned_flat) = (vec![], vec![]);
+        flatten(&live, &mut live_flat);
+        flatten(&scanned, &mut scanned_flat);
+        live_flat.sort();
+        scanned_flat.sort();
+        assert_eq!(live_flat, scanned_flat);
+        assert_eq!(
+            live.ignored_size,
+            30 + dirs_size(&root.path().join("ignored"))
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let root = make_tree(&[("top.txt", 5), ("data/a", 10), ("data/nested/b", 20)]);
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let mut tree = make_dir_tree_parallel_with(root.path().to_path_buf(), &options);
+        let data = root.path().join("data");
+
+        assert_eq!(tree.size, 5 + 10 + 20 + dirs_size(root.path()));
+        assert!(tree.get(&data).unwrap().collapsed);
+        assert!(tree.get(&data).unwrap().contents.is_none());
+
+        fs::write(data.join("new"), vec![0u8; 100]).unwrap();
+        let expanded = make_dir_tree_parallel(data.clone());
+        assert!(tree.splice(expanded));
+        assert!(!tree.get(&data).unwrap().collapsed);
+        // a, nested and new
+        assert_eq!(tree.get(&data).unwrap().len(), 3);
+        assert_eq!(tree.size, 5 + 10 + 20 + 100 + dirs_size(root.path()));
+    }
+
+    #[test]
+    fn test_cache() {
+        let root = make_tree(&[
+            ("small.txt", 5),
+            ("kept/nested/file", 3),
+            ("grown/file", 1),
+            ("removed/file", 1),
+        ]);
+        let path = root.path().to_path_buf();
+        let mut cached = make_dir_tree(path.clone());
+        // a size only the cached tree knows, to tell the reused entries from the scanned ones
+        let reused = path.join("kept/nested/file");
+        assert!(cached.update(&reused, |file| file.size = 1000));
+        // the times of the changes have to differ from the ones seen by the cached scan
+        thread::sleep(Duration::from_millis(50));
+
+        fs::write(path.join("grown/added"), vec![0u8; 100]).unwrap();
+        fs::remove_dir_all(path.join("removed")).unwrap();
+        // replaced through a temporary file, like editors and downloads do
+        fs::write(path.join("small.tmp"), b"much bigger").unwrap();
+        fs::rename(path.join("small.tmp"), path.join("small.txt")).unwrap();
+
+        let options = ScanOptions {
+            cache: Some(ScanCache::new(cached, ScanOptions::default().fingerprint())),
+            ..Default::default()
+        };
+        let fresh = make_dir_tree(path.clone());
+        for tree in [
+            make_dir_tree_with(path.clone(), &options),
+            make_dir_tree_parallel_with(path.clone(), &options),
+        ] {
+            assert_eq!(tree.get(&reused).unwrap().size, 1000);
+            // everything else is the same as in a fresh scan
+            assert_eq!(tree.size, fresh.size - 3 + 1000);
+            assert_eq!(tree.file_count, fresh.file_count);
+            assert_eq!(tree.dir_count, fresh.dir_count);
+            assert!(tree.get(&path.join("removed")).is_none());
+            assert_eq!(tree.get(&path.join("small.txt")).unwrap().size, 11);
+            assert_eq!(tree.get(&path.join("grown/added")).unwrap().size, 100);
+        }
+    }
+
+    #[test]
+    fn test_cache_checks() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().to_path_buf();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        for (name, age) in [("old.txt", 400 * day), ("new.txt", day)] {
+            let file = fs::File::create(path.join(name)).unwrap();
+            file.set_len(10).unwrap();
+            file.set_modified(now - age).unwrap();
+        }
+        let cache = |tree: Dir, options: &ScanOptions| ScanOptions {
+            cache: Some(ScanCache::new(tree, options.fingerprint())),
+            ..options.clone()
+        };
+
+        // trees scanned with other options are not reused
+        let mut cached = make_dir_tree(path.clone());
+        assert!(cached.update(&path.join("old.txt"), |file| file.size = 1000));
+        let options = ScanOptions {
+            gitignore: true,
+            ..cache(cached.clone(), &ScanOptions::default())
+        };
+        let tree = make_dir_tree_with(path.clone(), &options);
+        assert_eq!(tree.get(&path.join("old.txt")).unwrap().size, 10);
+        let tree = make_dir_tree_with(path.clone(), &cache(cached, &ScanOptions::default()));
+        assert_eq!(tree.get(&path.join("old.txt")).unwrap().size, 1000);
+
+        // reused files are only scanned if they are old enough
+        let stale = ScanOptions {
+            modified_before: Some(now - 180 * day),
+            ..Default::default()
+        };
+        let all = make_dir_tree(path.clone());
+        let tree = make_dir_tree_with(path.clone(), &cache(all, &stale));
+        assert!(tree.get(&path.join("new.txt")).is_none());
+        assert_eq!(tree.file_count, 1);
+        // files that got old enough since the cached scan are picked up
+        let older = ScanOptions {
+            modified_before: Some(SystemTime::now()),
+            ..Default::default()
+        };
+        let tree = make_dir_tree_with(path.clone(), &cache(tree, &older));
+        assert!(tree.get(&path.join("new.txt")).is_some());
+        assert_eq!(tree.file_count, 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_cache_hard_links() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().to_path_buf();
+        for sub_dir in ["a", "b"] {
+            fs::create_dir(path.join(sub_dir)).unwrap();
+        }
+        fs::write(path.join("a/link"), vec![0u8; 1000]).unwrap();
+        fs::hard_link(path.join("a/link"), path.join("b/link")).unwrap();
+        let cached = make_dir_tree(path.clone());
+        thread::sleep(Duration::from_millis(50));
+        // only b is listed again, the link in a is reused from the cache
+        fs::write(path.join("b/added"), b"added").unwrap();
+
+        let options = ScanOptions {
+            cache: Some(ScanCache::new(cached, ScanOptions::default().fingerprint())),
+            ..Default::default()
+        };
+        let tree = make_dir_tree_with(path.clone(), &options);
+        let links = ["a/link", "b/link"].map(|link| tree.get(&path.join(link)).unwrap());
+        assert_eq!(links.iter().filter(|link| !link.hard_link).count(), 1);
+        assert_eq!(tree.size, 1000 + 5 + dirs_size(&path));
+    }
 }
@@ -0,0 +1,393 @@
+use crate::structs::Dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// First bytes of a snapshot saved in the binary format
+const MAGIC: &[u8] = b"DIRSIZE\0";
+/// Version of the snapshot layout, snapshots of other versions are not loaded
+const VERSION: u32 = 6;
+
+/// The file format a snapshot is saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// compact binary format, the default
+    Binary,
+    /// human readable JSON, paths that are not valid UTF-8 are saved lossily
+    Json,
+}
+
+impl SnapshotFormat {
+    /// The format for a snapshot file, JSON if the file name ends with `.json`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => SnapshotFormat::Json,
+            _ => SnapshotFormat::Binary,
+        }
+    }
+}
+
+/// A scanned directory tree saved to a file, to be inspected later without scanning again
+///
+/// contains:
+/// - version - the version of the snapshot layout
+/// - created - when the scan was finished
+/// - options - the [`ScanOptions::fingerprint`](crate::scanning::ScanOptions::fingerprint) of the
+///   options the tree was scanned with
+/// - root - the scanned directory tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub created: SystemTime,
+    pub options: String,
+    #[serde(with = "tree_format")]
+    pub root: Dir,
+}
+
+impl Snapshot {
+    /// Makes a snapshot of a tree scanned with the options of the fingerprint, created now
+    pub fn new(root: Dir, options: String) -> Self {
+        Self {
+            version: VERSION,
+            created: SystemTime::now(),
+            options,
+            root,
+        }
+    }
+
+    /// Saves the snapshot to a file in the given format
+    pub fn save(&self, path: &Path, format: SnapshotFormat) -> io::Result<()> {
+        let snapshot = SnapshotRef {
+            version: self.version,
+            created: self.created,
+            options: &self.options,
+            root: &self.root,
+        };
+        snapshot.save(path, format)
+    }
+
+    /// Saves a tree scanned with the options of the fingerprint as a snapshot created now, without
+    /// taking it
+    pub fn save_tree(
+        root: &Dir,
+        options: &str,
+        path: &Path,
+        format: SnapshotFormat,
+    ) -> io::Result<()> {
+        let snapshot = SnapshotRef {
+            version: VERSION,
+            created: SystemTime::now(),
+            options,
+            root,
+        };
+        snapshot.save(path, format)
+    }
+
+    /// Loads a snapshot from a file, the format is detected from its contents
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let invalid = |err: &dyn std::fmt::Display| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid snapshot: {}", path.display(), err),
+            )
+        };
+        let snapshot: Snapshot = if reader.fill_buf()?.starts_with(MAGIC) {
+            reader.read_exact(&mut [0; MAGIC.len()])?;
+            bincode::deserialize_from(&mut reader).map_err(|err| invalid(&err))?
+        } else {
+            // snapshots are as deep as the scanned tree, which can be deeper than serde_json allows
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            deserializer.disable_recursion_limit();
+            Snapshot::deserialize(&mut deserializer).map_err(|err| invalid(&err))?
+        };
+        if snapshot.version != VERSION {
+            let message = format!("unsupported version {}", snapshot.version);
+            return Err(invalid(&message));
+        }
+        Ok(snapshot)
+    }
+}
+
+/// A snapshot of a borrowed tree, saved in the same layout as [`Snapshot`]
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    version: u32,
+    created: SystemTime,
+    options: &'a str,
+    #[serde(serialize_with = "tree_format::serialize")]
+    root: &'a Dir,
+}
+
+impl SnapshotRef<'_> {
+    fn save(&self, path: &Path, format: SnapshotFormat) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        match format {
+            SnapshotFormat::Binary => {
+                writer.write_all(MAGIC)?;
+                bincode::serialize_into(&mut writer, self).map_err(io::Error::other)?;
+            }
+            SnapshotFormat::Json => {
+                serde_json::to_writer(&mut writer, self)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// (De)serializes a tree with the path of every entry relative to the directory it is in, so the
+/// paths of the directories are not saved again with every entry within them
+mod tree_format {
+    use super::path_format;
+    use crate::structs::Dir;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+
+    /// An entry of a tree that is saved, the entry itself is saved without its path and the
+    /// entries within it
+    #[derive(Serialize)]
+    struct EntryRef<'a> {
+        #[serde(with = "path_format")]
+        path: &'a Path,
+        entry: &'a Dir,
+        contents: Option<EntriesRef<'a>>,
+        hidden: EntriesRef<'a>,
+    }
+
+    impl<'a> EntryRef<'a> {
+        fn new(entry: &'a Dir, parent: Option<&Path>) -> Self {
+            let path = parent
+                .and_then(|parent| entry.path.strip_prefix(parent).ok())
+                .unwrap_or(&entry.path);
+            Self {
+                path,
+                entry,
+                contents: entry
+                    .contents
+                    .as_deref()
+                    .map(|contents| EntriesRef(&entry.path, contents)),
+                hidden: EntriesRef(&entry.path, &entry.hidden),
+            }
+        }
+    }
+
+    /// The entries within a directory, saved relative to its path
+    struct EntriesRef<'a>(&'a Path, &'a [Dir]);
+
+    impl Serialize for EntriesRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let EntriesRef(parent, entries) = self;
+            serializer.collect_seq(
+                entries
+                    .iter()
+                    .map(|entry| EntryRef::new(entry, Some(parent))),
+            )
+        }
+    }
+
+    /// A loaded entry, its path is relative to its parent
+    #[derive(Deserialize)]
+    struct Entry {
+        #[serde(with = "path_format")]
+        path: PathBuf,
+        entry: Dir,
+        contents: Option<Vec<Entry>>,
+        hidden: Vec<Entry>,
+    }
+
+    impl Entry {
+        fn into_dir(self, parent: Option<&Path>) -> Dir {
+            let path = match parent {
+                Some(parent) => parent.join(&self.path),
+                None => self.path,
+            };
+            let entries = |entries: Vec<Entry>| -> Vec<Dir> {
+                entries
+                    .into_iter()
+                    .map(|entry| entry.into_dir(Some(&path)))
+                    .collect()
+            };
+            let mut dir = self.entry;
+            dir.contents = self.contents.map(entries);
+            dir.hidden = entries(self.hidden);
+            dir.path = path;
+            dir
+        }
+    }
+
+    pub fn serialize<S: Serializer>(root: &Dir, serializer: S) -> Result<S::Ok, S::Error> {
+        EntryRef::new(root, None).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Dir, D::Error> {
+        Ok(Entry::deserialize(deserializer)?.into_dir(None))
+    }
+}
+
+/// (De)serializes paths as strings in human readable formats and as raw bytes otherwise, so
+/// names that are not valid UTF-8 survive the binary format
+pub(crate) mod path_format {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return path.to_string_lossy().serialize(serializer);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            path.as_os_str().as_bytes().serialize(serializer)
+        }
+        #[cfg(not(unix))]
+        {
+            path.to_string_lossy().as_bytes().serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        if deserializer.is_human_readable() {
+            return String::deserialize(deserializer).map(PathBuf::from);
+        }
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStringExt;
+            Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+        }
+    }
+
+    /// The same for optional paths
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use std::path::{Path, PathBuf};
+
+        #[derive(Serialize, Deserialize)]
+        struct Wrapper(#[serde(with = "super")] PathBuf);
+
+        #[derive(Serialize)]
+        struct WrapperRef<'a>(#[serde(with = "super")] &'a Path);
+
+        pub fn serialize<S: Serializer>(
+            path: &Option<PathBuf>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            path.as_deref().map(WrapperRef).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<PathBuf>, D::Error> {
+            let path = Option::<Wrapper>::deserialize(deserializer)?;
+            Ok(path.map(|Wrapper(path)| path))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scanning::{make_dir_tree, make_dir_tree_with, ScanOptions};
+    use crate::snapshot::{Snapshot, SnapshotFormat};
+    use crate::structs::Dir;
+    use std::fs;
+    use std::path::Path;
+
+    /// Asserts that two trees hold the same entries with the same sizes and counts
+    fn assert_same_tree(a: &Dir, b: &Dir) {
+        assert_eq!(a.path, b.path);
+        assert_eq!((a.size, a.disk_size), (b.size, b.disk_size));
+        assert_eq!((a.file_count, a.dir_count), (b.file_count, b.dir_count));
+        assert_eq!(a.kind, b.kind);
+        assert_eq!(a.modified, b.modified);
+        assert_eq!(a.uid, b.uid);
+        let (a, b) = (a.contents.as_deref(), b.contents.as_deref());
+        assert_eq!(a.map(|x| x.len()), b.map(|x| x.len()));
+        for (a, b) in a.unwrap_or_default().iter().zip(b.unwrap_or_default()) {
+            assert_same_tree(a, b);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let root = tempfile::tempdir().unwrap();
+        let mut path = root.path().to_path_buf();
+        for depth in 0..80 {
+            path.push(format!("level_{}", depth));
+        }
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("deep.txt"), "deep").unwrap();
+        fs::write(root.path().join("top.txt"), "top level file").unwrap();
+        let tree = make_dir_tree(root.path().to_path_buf());
+
+        let out = tempfile::tempdir().unwrap();
+        for name in ["snap.bin", "snap.json"] {
+            let file = out.path().join(name);
+            let format = SnapshotFormat::from_path(&file);
+            Snapshot::new(tree.clone(), String::new())
+                .save(&file, format)
+                .unwrap();
+            let loaded = Snapshot::load(&file).unwrap();
+            assert_same_tree(&loaded.root, &tree);
+        }
+        let json = fs::read_to_string(out.path().join("snap.json")).unwrap();
+        assert!(json.starts_with('{'));
+        // the entries are saved relative to their parents
+        assert_eq!(json.matches("level_0").count(), 1);
+
+        // anything else is rejected
+        let bad = out.path().join("bad.bin");
+        fs::write(&bad, "not a snapshot").unwrap();
+        assert!(Snapshot::load(&bad).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_odd_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let name = OsStr::from_bytes(b"bad\xffname");
+        fs::write(root.path().join(name), "data").unwrap();
+        let tree = make_dir_tree(root.path().to_path_buf());
+
+        let file = root.path().join("snap.bin");
+        Snapshot::new(tree.clone(), String::new())
+            .save(&file, SnapshotFormat::Binary)
+            .unwrap();
+        let loaded = Snapshot::load(&file).unwrap();
+        assert!(loaded.root.get(&root.path().join(name)).is_some());
+        assert!(loaded.root.get(Path::new("/nonexistent")).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_hidden_entries() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("data/nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("file"), "data").unwrap();
+        fs::hard_link(nested.join("file"), root.path().join("link")).unwrap();
+        let options = ScanOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        let tree = make_dir_tree_with(root.path().to_path_buf(), &options);
+
+        let file = root.path().join("snap.bin");
+        Snapshot::new(tree, String::new())
+            .save(&file, SnapshotFormat::Binary)
+            .unwrap();
+        let loaded = Snapshot::load(&file).unwrap();
+        let data = loaded.root.get(&root.path().join("data")).unwrap();
+        assert!(data.collapsed);
+        assert_eq!(data.hidden.len(), 1);
+        assert_eq!(data.hidden[0].path, nested.join("file"));
+    }
+}
@@ -1,9 +1,18 @@
+use crate::diff::Change;
+use crate::snapshot::path_format;
 use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fs;
+use std::hash::Hash;
 use std::io::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Parser, Clone)]
 /// Enum representation of different size formats
@@ -22,6 +31,25 @@ pub enum SizeFormat {
     GIGABYTES,
 }
 
+impl SizeFormat {
+    /// Converts a size in bytes to this format, returns the converted size and the unit string
+    pub fn format(&self, size: u64) -> (f32, &'static str) {
+        let formated_size: f32 = match self {
+            SizeFormat::BYTES => size as f32,
+            SizeFormat::KILOBYTES => size as f32 / 1000.0,
+            SizeFormat::MEGABYTES => size as f32 / 1000000.0,
+            SizeFormat::GIGABYTES => size as f32 / 1000000.0 / 1000.0,
+        };
+        let format_str: &str = match self {
+            SizeFormat::BYTES => "b",
+            SizeFormat::KILOBYTES => "kb",
+            SizeFormat::MEGABYTES => "mb",
+            SizeFormat::GIGABYTES => "gb",
+        };
+        (formated_size, format_str)
+    }
+}
+
 impl FromStr for SizeFormat {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -35,18 +63,269 @@ impl FromStr for SizeFormat {
     }
 }
 
+/// Which of the sizes of a directory/file is used for displaying, sorting and filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SizeMode {
+    /// the length of the files in bytes (`du --apparent-size`)
+    Apparent,
+    /// the space allocated for the files on disk (`du`)
+    #[default]
+    Disk,
+}
+
+impl SizeMode {
+    /// Switches between the apparent and the on disk size
+    pub fn toggle(self) -> Self {
+        match self {
+            SizeMode::Apparent => SizeMode::Disk,
+            SizeMode::Disk => SizeMode::Apparent,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            SizeMode::Apparent => "apparent size",
+            SizeMode::Disk => "disk usage",
+        }
+    }
+}
+
+/// Which entries are shown when ignored entries were detected during the scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IgnoreView {
+    /// all entries
+    #[default]
+    All,
+    /// only the entries that are not ignored
+    Tracked,
+    /// only the ignored entries (build artifacts, caches, ...)
+    Ignored,
+}
+
+impl IgnoreView {
+    /// Cycles through the views
+    pub fn next(self) -> Self {
+        match self {
+            IgnoreView::All => IgnoreView::Tracked,
+            IgnoreView::Tracked => IgnoreView::Ignored,
+            IgnoreView::Ignored => IgnoreView::All,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            IgnoreView::All => "all files",
+            IgnoreView::Tracked => "not ignored",
+            IgnoreView::Ignored => "only ignored",
+        }
+    }
+}
+
+/// What the contents of a directory are sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    /// the size in the shown size mode
+    #[default]
+    Size,
+    /// the number of files and directories within
+    Count,
+    /// the newest modification time within, the least recently modified first
+    Modified,
+    /// the size difference to an older tree, only for diff trees
+    Change,
+}
+
+impl SortBy {
+    /// Cycles through the sort orders
+    pub fn next(self) -> Self {
+        match self {
+            SortBy::Size => SortBy::Count,
+            SortBy::Count => SortBy::Modified,
+            SortBy::Modified => SortBy::Change,
+            SortBy::Change => SortBy::Size,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            SortBy::Size => "by size",
+            SortBy::Count => "by count",
+            SortBy::Modified => "by last modified",
+            SortBy::Change => "by change",
+        }
+    }
+}
+
+/// Sizes and number of files summed up over a group of entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    pub size: u64,
+    pub disk_size: u64,
+    pub file_count: u64,
+}
+
+impl Usage {
+    /// The summed up size in the given mode
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Disk => self.disk_size,
+        }
+    }
+
+    /// String representation of the usage, e.g. `12.50 mb in 34 files`
+    pub fn display(&self, size_fmt: &SizeFormat, mode: SizeMode) -> String {
+        let (formated_size, format_str) = size_fmt.format(self.size_in(mode));
+        format!(
+            "{:>8.2} {} in {} files",
+            formated_size, format_str, self.file_count
+        )
+    }
+}
+
+/// The sums a directory/file adds to the directory it is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Totals {
+    size: u64,
+    disk_size: u64,
+    ignored_size: u64,
+    ignored_disk_size: u64,
+    file_count: u64,
+    dir_count: u64,
+    error_count: u64,
+    newest_modified: Option<SystemTime>,
+}
+
+/// Sorts usage grouped by a key from the biggest to the smallest group
+pub(crate) fn sort_usage<K>(
+    usage: impl IntoIterator<Item = (K, Usage)>,
+    mode: SizeMode,
+) -> Vec<(K, Usage)> {
+    let mut usage: Vec<(K, Usage)> = usage.into_iter().collect();
+    usage.sort_by_key(|(_, usage)| Reverse(usage.size_in(mode)));
+    usage
+}
+
+/// Last status change time (ctime) of a file, it changes with every change to the file or its
+/// metadata and can't be set back
+#[cfg(unix)]
+pub(crate) fn changed_time(metadata: &fs::Metadata) -> Option<SystemTime> {
+    use std::os::unix::fs::MetadataExt;
+    let secs = u64::try_from(metadata.ctime()).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::new(secs, metadata.ctime_nsec() as u32))
+}
+
+/// Last status change time (ctime) of a file
+#[cfg(not(unix))]
+pub(crate) fn changed_time(_metadata: &fs::Metadata) -> Option<SystemTime> {
+    None
+}
+
+/// Size of the blocks allocated for a file on disk
+#[cfg(unix)]
+pub(crate) fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    // st_blocks is always counted in 512 byte units, regardless of the filesystem block size
+    metadata.blocks() * 512
+}
+
+/// Size of the blocks allocated for a file on disk
+#[cfg(not(unix))]
+pub(crate) fn allocated_size(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+/// The kind of filesystem entry a Dir represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EntryKind {
+    #[default]
+    Directory,
+    File,
+    /// a symbolic link, has contents if it was followed to a directory
+    Symlink,
+    /// sockets, pipes, devices, etc.
+    Other,
+    /// a directory on another filesystem that was not scanned
+    MountPoint,
+}
+
 /// Structure that represents the directory tree or file
 ///
 /// contains:
-/// - size - the size of the directory/file in bytes
+/// - size - the apparent size of the directory/file in bytes
+/// - disk_size - the size allocated on disk for the directory/file in bytes
+/// - own_size, own_disk_size - the sizes of a directory itself, without its contents, they are part
+///   of size and disk_size like in `du`
 /// - path - the path to the directory/file
 /// - contents - the contents of the directory (if it's a directory)
+/// - kind - the kind of the entry (directory, file, symlink, ...)
+/// - target - the path a symlink points to
+/// - hard_link - the file is a hard link to an inode that was already counted elsewhere in the tree,
+///   or the directory was already scanned through another symlink when following symlinks
+/// - inode - the (device, inode) pair of a file that can be reached through several paths, hard
+///   links or any file when following symlinks, to count it once when it is reused from a cache
+/// - excluded_count - the number of entries of the directory that were skipped by the scan filter
+/// - excluded_size, excluded_disk_size - the sizes of the skipped entries, if they were summarized
+/// - ignored - the entry is ignored by a `.gitignore`/`.ignore` file
+/// - ignored_size, ignored_disk_size - the sizes of the ignored entries within the directory
+/// - collapsed - the directory was below the maximum scan depth, its contents were not kept
+/// - hidden - the entries below a collapsed directory that are still needed once its contents are
+///   dropped, entries that could not be read, files with several links and symlinks that were not
+///   followed yet, without contents
+/// - pending - the directory is still being scanned
+/// - incomplete - the scan was cancelled before the directory was fully scanned
+/// - error - why the directory/file could not be read, if it couldn't
+/// - error_count - the number of entries that could not be read, the entry itself included
+/// - file_count - the number of files (and other non directory entries) within the directory, 1 for a file
+/// - dir_count - the number of directories within the directory, not counting itself
+/// - modified, accessed - the last modification and access time of the directory/file itself
+/// - newest_modified - the newest modification time within the directory, itself included
+/// - changed - the last status change time (ctime) of the directory/file itself, on unix
+/// - uid, gid - the ids of the user and group owning the directory/file
+/// - change - how the entry differs from an older tree, only set on trees made by `diff_trees`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dir {
     pub size: u64,
+    pub disk_size: u64,
+    pub own_size: u64,
+    pub own_disk_size: u64,
+    // snapshots save the path relative to the parent together with the entries within it
+    #[serde(skip)]
     pub path: PathBuf,
+    #[serde(skip)]
     pub contents: Option<Vec<Dir>>,
-    pub is_file: bool,
+    pub kind: EntryKind,
+    #[serde(with = "path_format::option")]
+    pub target: Option<PathBuf>,
+    pub hard_link: bool,
+    pub inode: Option<(u64, u64)>,
+    pub excluded_count: u64,
+    pub excluded_size: u64,
+    pub excluded_disk_size: u64,
+    pub ignored: bool,
+    pub ignored_size: u64,
+    pub ignored_disk_size: u64,
+    pub collapsed: bool,
+    #[serde(skip)]
+    pub hidden: Vec<Dir>,
+    pub pending: bool,
+    pub incomplete: bool,
+    pub error: Option<String>,
+    pub error_count: u64,
+    pub file_count: u64,
+    pub dir_count: u64,
+    pub modified: Option<SystemTime>,
+    pub accessed: Option<SystemTime>,
+    pub newest_modified: Option<SystemTime>,
+    pub changed: Option<SystemTime>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    #[serde(skip)]
+    pub change: Option<Change>,
 }
+
+/// A directory tree that is shared between threads, e.g. built by a scanner while it is shown
+pub type SharedDir = Arc<Mutex<Dir>>;
 impl Dir {
     /// Create a new directory/file
     ///
@@ -54,22 +333,532 @@ impl Dir {
     /// - size - the size of the directory/file
     /// - path - the path to the directory/file
     /// - contents - the contents of the directory (if it's a directory)
-    pub fn new(size: u64, path: PathBuf, contents: Option<Vec<Dir>>, is_file: bool) -> Self {
+    /// - kind - the kind of the entry
+    pub fn new(size: u64, path: PathBuf, contents: Option<Vec<Dir>>, kind: EntryKind) -> Self {
+        // the counts of directories are summed up from their contents
+        let is_file =
+            contents.is_none() && kind != EntryKind::Directory && kind != EntryKind::MountPoint;
         Self {
             size,
+            disk_size: size,
+            own_size: 0,
+            own_disk_size: 0,
             path,
             contents,
-            is_file,
+            kind,
+            target: None,
+            hard_link: false,
+            inode: None,
+            excluded_count: 0,
+            excluded_size: 0,
+            excluded_disk_size: 0,
+            ignored: false,
+            ignored_size: 0,
+            ignored_disk_size: 0,
+            collapsed: false,
+            hidden: vec![],
+            pending: false,
+            incomplete: false,
+            error: None,
+            error_count: 0,
+            file_count: is_file as u64,
+            dir_count: 0,
+            modified: None,
+            accessed: None,
+            newest_modified: None,
+            changed: None,
+            uid: None,
+            gid: None,
+            change: None,
+        }
+    }
+
+    /// Create a new directory with the sizes summed up from its contents
+    ///
+    /// Hard links to already counted inodes don't add to the sizes
+    pub fn from_contents(path: PathBuf, contents: Vec<Dir>) -> Self {
+        let mut dir = Dir::new(0, path, Some(contents), EntryKind::Directory);
+        dir.recalculate();
+        dir
+    }
+
+    /// Sums up the sizes, counts and the newest modification time of the directory from its
+    /// contents again, after they were changed
+    ///
+    /// Hard links to already counted inodes don't add to the sizes
+    pub fn recalculate(&mut self) {
+        let own_error = self.error.is_some() as u64;
+        let contents = match &self.contents {
+            Some(contents) => contents,
+            None => {
+                self.error_count =
+                    own_error + self.hidden.iter().map(|x| x.error_count).sum::<u64>();
+                self.newest_modified = self.newest_modified.max(self.modified);
+                return;
+            }
+        };
+        self.newest_modified = contents
+            .iter()
+            .map(|x| x.newest_modified)
+            .max()
+            .flatten()
+            .max(self.modified);
+        self.error_count = own_error + contents.iter().map(|x| x.error_count).sum::<u64>();
+        self.file_count = contents.iter().map(|x| x.file_count).sum();
+        self.dir_count = contents
+            .iter()
+            .map(|x| x.dir_count + x.is_dir() as u64)
+            .sum();
+        let counted = || contents.iter().filter(|x| !x.hard_link);
+        self.size = self.own_size + counted().map(|x| x.size).sum::<u64>();
+        self.disk_size = self.own_disk_size + counted().map(|x| x.disk_size).sum::<u64>();
+        if self.ignored {
+            self.ignored_size = self.size;
+            self.ignored_disk_size = self.disk_size;
+        } else {
+            self.ignored_size = counted().map(|x| x.ignored_size).sum();
+            self.ignored_disk_size = counted().map(|x| x.ignored_disk_size).sum();
+        }
+    }
+
+    /// Sets the times and the owner of the directory/file itself from its metadata, directories
+    /// also add their own size to the sizes of their contents
+    pub fn set_metadata(&mut self, metadata: &fs::Metadata) {
+        if self.kind == EntryKind::Directory {
+            let (own_size, own_disk_size) = (metadata.len(), allocated_size(metadata));
+            self.size = self.size + own_size - self.own_size;
+            self.disk_size = self.disk_size + own_disk_size - self.own_disk_size;
+            self.own_size = own_size;
+            self.own_disk_size = own_disk_size;
+            if self.ignored {
+                self.ignored_size = self.size;
+                self.ignored_disk_size = self.disk_size;
+            }
+        }
+        self.modified = metadata.modified().ok();
+        self.accessed = metadata.accessed().ok();
+        self.newest_modified = self.newest_modified.max(self.modified);
+        self.changed = changed_time(metadata);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            self.uid = Some(metadata.uid());
+            self.gid = Some(metadata.gid());
+        }
+    }
+
+    /// Sums up the usage of the files in the tree grouped by a key
+    ///
+    /// Entries without contents are counted as a whole (files, and collapsed directories whose
+    /// contents are no longer known), hard links to already counted inodes are skipped.
+    pub fn usage_by<K: Eq + Hash>(&self, key: impl Fn(&Dir) -> K) -> HashMap<K, Usage> {
+        fn add<K: Eq + Hash>(dir: &Dir, key: &impl Fn(&Dir) -> K, usage: &mut HashMap<K, Usage>) {
+            match &dir.contents {
+                Some(contents) => {
+                    for sub_dir in contents.iter().filter(|x| !x.hard_link) {
+                        add(sub_dir, key, usage);
+                    }
+                }
+                None => {
+                    let group = usage.entry(key(dir)).or_default();
+                    group.size += dir.size;
+                    group.disk_size += dir.disk_size;
+                    group.file_count += dir.file_count;
+                }
+            }
+        }
+        let mut usage = HashMap::new();
+        add(self, &key, &mut usage);
+        usage
+    }
+
+    /// Drops the contents of the directory, keeping the sizes that were summed up from them and
+    /// the entries that are still needed in [`Dir::hidden`]
+    pub fn collapse(&mut self) {
+        if let Some(contents) = self.contents.take() {
+            self.collapsed = true;
+            for sub_dir in contents {
+                sub_dir.hide_into(&mut self.hidden);
+            }
+        }
+    }
+
+    /// Adds the entries of a dropped subtree that are still needed to the hidden entries of the
+    /// collapsed directory it was in, in the order of the tree
+    fn hide_into(mut self, hidden: &mut Vec<Dir>) {
+        let contents = self.contents.take();
+        let own = std::mem::take(&mut self.hidden);
+        if self.error.is_some() || self.inode.is_some() || self.pending {
+            // the errors of its contents are hidden on their own
+            self.error_count = self.error.is_some() as u64;
+            hidden.push(self);
+        }
+        hidden.extend(own);
+        for sub_dir in contents.into_iter().flatten() {
+            sub_dir.hide_into(hidden);
+        }
+    }
+
+    /// Counts every inode that is reached through several paths once, at the path that comes
+    /// first in the tree, the other paths are marked as hard links
+    ///
+    /// Scanning in parallel counts the path that is reached first, which depends on the order
+    /// the threads get to them, so this is done once the tree is complete. Files below collapsed
+    /// directories are counted by their hidden entries.
+    pub fn count_links_once(&mut self) {
+        let mut links: HashMap<(u64, u64), Vec<&Dir>> = HashMap::new();
+        self.for_each_link(&mut |link| {
+            if let Some(inode) = link.inode {
+                links.entry(inode).or_default().push(link);
+            }
+        });
+        let mut changed = vec![];
+        for links in links.values() {
+            let first = links.iter().map(|link| &link.path).min();
+            for link in links {
+                let hard_link = Some(&link.path) != first;
+                if link.hard_link != hard_link {
+                    changed.push((link.path.clone(), hard_link));
+                }
+            }
+        }
+        for (path, hard_link) in changed {
+            self.update_hidden(&path, |link| link.hard_link = hard_link);
+        }
+    }
+
+    /// Calls `f` with every entry of the tree that has an inode, hidden ones included
+    fn for_each_link<'a>(&'a self, f: &mut impl FnMut(&'a Dir)) {
+        if self.inode.is_some() {
+            f(self);
+        }
+        for sub_dir in self.contents.iter().flatten().chain(&self.hidden) {
+            sub_dir.for_each_link(f);
+        }
+    }
+
+    /// The size that is freed when the entries are removed from the tree, entries counted
+    /// elsewhere don't free anything and neither do inodes that are still linked from outside of
+    /// the entries
+    pub fn removed_size(&self, entries: &[&Dir], mode: SizeMode) -> u64 {
+        let mut counted = vec![];
+        for entry in entries {
+            entry.for_each_link(&mut |link| {
+                if !link.hard_link {
+                    counted.push(link);
+                }
+            });
+        }
+        let mut kept = HashSet::new();
+        if !counted.is_empty() {
+            let within = |path: &Path| entries.iter().any(|entry| path.starts_with(&entry.path));
+            self.for_each_link(&mut |link| {
+                if !within(&link.path) {
+                    kept.insert(link.inode);
+                }
+            });
+        }
+        let size: u64 = entries
+            .iter()
+            .filter(|entry| !entry.hard_link)
+            .map(|entry| entry.size_in(mode))
+            .sum();
+        let still_linked: u64 = counted
+            .iter()
+            .filter(|link| kept.contains(&link.inode))
+            .map(|link| link.size_in(mode))
+            .sum();
+        size - still_linked
+    }
+
+    /// Checks if the tree has entries that are reached through several paths
+    pub(crate) fn has_links(&self) -> bool {
+        let mut found = false;
+        self.for_each_link(&mut |_| found = true);
+        found
+    }
+
+    /// Same as [`Dir::update`], entries below a collapsed directory are changed among its hidden
+    /// entries and the change of their totals is added to the collapsed directory
+    pub(crate) fn update_hidden(&mut self, path: &Path, change: impl FnOnce(&mut Dir)) -> bool {
+        if let Some(positions) = self.positions(path) {
+            return self.update_at(&positions, change);
+        }
+        // the closest parent that is in the structure is the collapsed directory
+        let collapsed = path
+            .ancestors()
+            .skip(1)
+            .find_map(|ancestor| self.positions(ancestor));
+        let positions = match collapsed {
+            Some(positions) => positions,
+            None => return false,
+        };
+        let mut updated = false;
+        self.update_at(&positions, |collapsed| {
+            let index = match collapsed.hidden.iter().position(|entry| entry.path == path) {
+                Some(index) => index,
+                None => return,
+            };
+            let entry = &mut collapsed.hidden[index];
+            let before = entry.totals();
+            change(entry);
+            let after = entry.totals();
+            // an entry that got contents is dropped again, keeping what is still needed of it
+            let entry = collapsed.hidden.remove(index);
+            let mut hidden = vec![];
+            entry.hide_into(&mut hidden);
+            collapsed.hidden.splice(index..index, hidden);
+            collapsed.add_change(&before, &after);
+            updated = true;
+        });
+        updated
+    }
+
+    /// Applies a change to the Dir with the given path in the Dir structure and adds the change of
+    /// its sizes to all of its parents
+    ///
+    /// Returns false if there is no Dir with the path in the structure
+    pub fn update(&mut self, path: &Path, change: impl FnOnce(&mut Dir)) -> bool {
+        match self.positions(path) {
+            Some(positions) => self.update_at(&positions, change),
+            None => false,
+        }
+    }
+
+    /// Finds the Dir with the given path in the Dir structure, returns its position within the
+    /// contents of each of its parents below this Dir
+    pub(crate) fn positions(&self, path: &Path) -> Option<Vec<usize>> {
+        let mut positions = vec![];
+        let mut dir = self;
+        while dir.path != path {
+            let (position, sub_dir) = dir
+                .contents
+                .as_ref()?
+                .iter()
+                .enumerate()
+                .find(|(_, sub_dir)| path.starts_with(&sub_dir.path))?;
+            positions.push(position);
+            dir = sub_dir;
+        }
+        Some(positions)
+    }
+
+    /// Same as [`Dir::update`], with the Dir found by its [`Dir::positions`] instead of its path
+    pub(crate) fn update_at(&mut self, positions: &[usize], change: impl FnOnce(&mut Dir)) -> bool {
+        self.update_totals_at(positions, change).is_some()
+    }
+
+    /// Applies the change and returns the totals of the changed Dir before and after it
+    fn update_totals_at(
+        &mut self,
+        positions: &[usize],
+        change: impl FnOnce(&mut Dir),
+    ) -> Option<(Totals, Totals)> {
+        let (&position, positions) = match positions.split_first() {
+            Some(first) => first,
+            None => {
+                let before = self.totals();
+                change(self);
+                return Some((before, self.totals()));
+            }
+        };
+        let sub_dir = self.contents.as_mut()?.get_mut(position)?;
+        let (before, after) = sub_dir.update_totals_at(positions, change)?;
+        self.add_change(&before, &after);
+        Some((before, after))
+    }
+
+    /// What the Dir adds to the sums of the directory it is in
+    fn totals(&self) -> Totals {
+        // hard links to already counted inodes don't add to the sizes
+        let counted = |size: u64| if self.hard_link { 0 } else { size };
+        Totals {
+            size: counted(self.size),
+            disk_size: counted(self.disk_size),
+            ignored_size: counted(self.ignored_size),
+            ignored_disk_size: counted(self.ignored_disk_size),
+            file_count: self.file_count,
+            dir_count: self.dir_count + self.is_dir() as u64,
+            error_count: self.error_count,
+            newest_modified: self.newest_modified,
+        }
+    }
+
+    /// Adds the change of the totals of one of the contents to the sums of the directory, so they
+    /// don't have to be summed up from all of the contents again
+    fn add_change(&mut self, before: &Totals, after: &Totals) {
+        self.size = self.size + after.size - before.size;
+        self.disk_size = self.disk_size + after.disk_size - before.disk_size;
+        self.file_count = self.file_count + after.file_count - before.file_count;
+        self.dir_count = self.dir_count + after.dir_count - before.dir_count;
+        self.error_count = self.error_count + after.error_count - before.error_count;
+        if self.ignored {
+            self.ignored_size = self.size;
+            self.ignored_disk_size = self.disk_size;
+        } else {
+            self.ignored_size = self.ignored_size + after.ignored_size - before.ignored_size;
+            self.ignored_disk_size =
+                self.ignored_disk_size + after.ignored_disk_size - before.ignored_disk_size;
+        }
+        if after.newest_modified >= before.newest_modified {
+            self.newest_modified = self.newest_modified.max(after.newest_modified);
+        } else {
+            // the newest entry might be gone, only the contents know which one is the newest now
+            self.newest_modified = self
+                .contents
+                .iter()
+                .flatten()
+                .map(|x| x.newest_modified)
+                .max()
+                .flatten()
+                .max(self.modified);
+        }
+    }
+
+    /// Replaces the Dir with the same path as subtree in the Dir structure and sums up the sizes of
+    /// all of its parents again, inodes reached through several paths are counted once again if
+    /// any of them were replaced
+    ///
+    /// Returns false if there is no Dir with the path of subtree in the structure
+    pub fn splice(&mut self, subtree: Dir) -> bool {
+        let path = subtree.path.clone();
+        let mut links = subtree.has_links();
+        let spliced = self.update(&path, |dir| {
+            links |= dir.has_links();
+            *dir = subtree;
+        });
+        if spliced && links {
+            self.count_links_once();
+        }
+        spliced
+    }
+
+    /// Replaces the Dir by subtree, but the contents of subtree that don't have contents of their
+    /// own are replaced by the existing ones with the same path, used for building a tree up from
+    /// the bottom while it is being shown
+    pub(crate) fn merge_from(&mut self, subtree: Dir) {
+        let mut existing: HashMap<PathBuf, Dir> = self
+            .contents
+            .take()
+            .into_iter()
+            .flatten()
+            // entries that are still pending were never scanned, they have nothing to keep
+            .filter(|sub_dir| !sub_dir.pending)
+            .map(|sub_dir| (sub_dir.path.clone(), sub_dir))
+            .collect();
+        *self = subtree;
+        for sub_dir in self.contents.iter_mut().flatten() {
+            if sub_dir.contents.is_none() {
+                if let Some(existing) = existing.remove(&sub_dir.path) {
+                    *sub_dir = existing;
+                }
+            }
+        }
+        if self.ignored {
+            self.mark_ignored();
+        }
+    }
+
+    /// Adds entry to the contents of its parent directory in the Dir structure, replacing the
+    /// entry with the same path, and sums up the sizes of all of its parents again, like
+    /// [`Dir::splice`] counts the inodes again
+    ///
+    /// Returns false if the parent is not in the structure or its contents are not known
+    pub fn insert(&mut self, entry: Dir) -> bool {
+        let parent = match entry.path.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => return false,
+        };
+        let mut links = entry.has_links();
+        let mut inserted = false;
+        self.update(&parent, |dir| {
+            if let Some(contents) = dir.contents.as_mut() {
+                match contents
+                    .iter_mut()
+                    .find(|sub_dir| sub_dir.path == entry.path)
+                {
+                    Some(existing) => {
+                        links |= existing.has_links();
+                        *existing = entry;
+                    }
+                    None => contents.push(entry),
+                }
+                dir.recalculate();
+                inserted = true;
+            }
+        });
+        if inserted && links {
+            self.count_links_once();
+        }
+        inserted
+    }
+
+    /// Removes the entry with the given path from the Dir structure and sums up the sizes of all
+    /// of its parents again, an inode that was counted at the removed entry is counted at one of
+    /// its other paths
+    ///
+    /// Returns the removed entry, None if it is not in the structure
+    pub fn remove(&mut self, path: &Path) -> Option<Dir> {
+        let parent = path.parent()?;
+        let mut removed = None;
+        self.update(parent, |dir| {
+            if let Some(contents) = dir.contents.as_mut() {
+                if let Some(index) = contents.iter().position(|sub_dir| sub_dir.path == path) {
+                    removed = Some(contents.remove(index));
+                    dir.recalculate();
+                }
+            }
+        });
+        if removed.as_ref().is_some_and(Dir::has_links) {
+            self.count_links_once();
         }
+        removed
     }
 
     pub fn from_entry(entry: fs::DirEntry) -> Result<Dir, Error> {
         let path = entry.path();
         let metadata = entry.metadata()?;
         let size = metadata.len();
-        let is_file = path.is_file();
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_dir() {
+            EntryKind::Directory
+        } else if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+
+        let mut dir = Dir::new(size, path, None, kind);
+        dir.disk_size = allocated_size(&metadata);
+        Ok(dir)
+    }
+
+    /// Marks the directory/file and all of its contents as ignored
+    pub fn mark_ignored(&mut self) {
+        self.ignored = true;
+        self.ignored_size = self.size;
+        self.ignored_disk_size = self.disk_size;
+        for sub_dir in self.contents.iter_mut().flatten() {
+            sub_dir.mark_ignored();
+        }
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == EntryKind::File
+    }
 
-        Ok(Dir::new(size, path, None, is_file))
+    /// Checks if the entry is a directory, scanned or not, or a symlink that was followed to one
+    pub fn is_dir(&self) -> bool {
+        self.contents.is_some()
+            || self.kind == EntryKind::Directory
+            || self.kind == EntryKind::MountPoint
+    }
+
+    /// The number of files and directories within the directory
+    pub fn entry_count(&self) -> u64 {
+        self.file_count + self.dir_count
     }
 
     pub fn len(&self) -> usize {
@@ -86,24 +875,38 @@ impl Dir {
         }
     }
 
-    pub fn name(&self) -> &str {
-        self.path.file_name().unwrap().to_str().unwrap()
+    /// Name of the directory/file, names that aren't valid UTF-8 are converted lossily and paths
+    /// without a name (`/`, `.`, `..`) are used whole
+    pub fn name(&self) -> Cow<'_, str> {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy(),
+            None => self.path.to_string_lossy(),
+        }
     }
 
-    pub fn size_formated(&self, size_fmt: &SizeFormat) -> (f32, &str) {
-        let formated_size: f32 = match size_fmt {
-            SizeFormat::BYTES => self.size as f32,
-            SizeFormat::KILOBYTES => self.size as f32 / 1000.0,
-            SizeFormat::MEGABYTES => self.size as f32 / 1000000.0,
-            SizeFormat::GIGABYTES => self.size as f32 / 1000000.0 / 1000.0,
-        };
-        let format_str: &str = match size_fmt {
-            SizeFormat::BYTES => "b",
-            SizeFormat::KILOBYTES => "kb",
-            SizeFormat::MEGABYTES => "mb",
-            SizeFormat::GIGABYTES => "gb",
+    /// The size of the directory/file in the given mode
+    pub fn size_in(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.size,
+            SizeMode::Disk => self.disk_size,
+        }
+    }
+
+    /// The size of the directory/file in the given mode, only counting the entries in the ignore view
+    pub fn size_in_view(&self, mode: SizeMode, view: IgnoreView) -> u64 {
+        let ignored = match mode {
+            SizeMode::Apparent => self.ignored_size,
+            SizeMode::Disk => self.ignored_disk_size,
         };
-        (formated_size, format_str)
+        match view {
+            IgnoreView::All => self.size_in(mode),
+            IgnoreView::Tracked => self.size_in(mode) - ignored,
+            IgnoreView::Ignored => ignored,
+        }
+    }
+
+    pub fn size_formated(&self, size_fmt: &SizeFormat) -> (f32, &str) {
+        size_fmt.format(self.size)
     }
     /// String representation of the directory/file
     pub fn display(&self, size_fmt: &SizeFormat) -> String {
@@ -120,6 +923,8 @@ impl Dir {
     }
 
     /// Recursively finds the parent Dir of a given path in the Dir structure
+    ///
+    /// Returns the deepest Dir on the way to the path if the parent is not in the structure
     pub fn find(&self, path: &PathBuf) -> &Self {
         let parent_dir = match path.parent() {
             Some(path) => path,
@@ -130,10 +935,7 @@ impl Dir {
         } else {
             let contents = match self.contents.as_ref() {
                 Some(c) => c,
-                None => panic!(
-                    "dirrectory with name {} was not found",
-                    parent_dir.display()
-                ),
+                None => return self,
             };
             for sub_dir in contents.iter() {
                 if path.starts_with(&sub_dir.path) {
@@ -144,13 +946,27 @@ impl Dir {
         self
     }
 
-    /// Filters the contents of dir that is bigger than size_min and returns  new vector containing references to Dirs
-    pub fn filter_size(&self, size_min: u64) -> Option<Vec<&Dir>> {
+    /// Finds the Dir with exactly the given path in the Dir structure
+    pub fn get(&self, path: &Path) -> Option<&Self> {
+        if self.path == path {
+            return Some(self);
+        }
+        self.contents
+            .as_ref()?
+            .iter()
+            .find(|sub_dir| path.starts_with(&sub_dir.path))?
+            .get(path)
+    }
+
+    /// Filters the contents of dir that is bigger than size_min (in the given size mode) and returns  new vector containing references to Dirs
+    pub fn filter_size(&self, size_min: u64, mode: SizeMode) -> Option<Vec<&Dir>> {
         match &self.contents {
             None => None,
             Some(contents) => {
-                let filtered: Vec<&Dir> =
-                    contents.iter().filter(|dir| dir.size > size_min).collect();
+                let filtered: Vec<&Dir> = contents
+                    .iter()
+                    .filter(|dir| dir.size_in(mode) > size_min)
+                    .collect();
                 if filtered.is_empty() {
                     None
                 } else {
@@ -163,7 +979,7 @@ impl Dir {
     /// Sorts the complete contents tree by size
     pub fn sort_by_size(&mut self) {
         if let Some(contents) = self.contents.as_mut() {
-            contents.sort_by(|dir, dir2| dir2.size.cmp(&dir.size));
+            contents.sort_by_key(|dir| Reverse(dir.size));
             for subdir in contents.iter_mut() {
                 subdir.sort_by_size();
             }
@@ -177,10 +993,17 @@ impl fmt::Display for Dir {
     }
 }
 
+/// Makes a file of the given size, to build trees in tests
+#[cfg(test)]
+pub(crate) fn make_file(path: &str, size: u64) -> Dir {
+    Dir::new(size, PathBuf::from(path), None, EntryKind::File)
+}
+
 #[cfg(test)]
 mod test {
     use crate::scanning::make_dir_tree_parallel;
-    use std::path::Path;
+    use crate::structs::{make_file, Dir, EntryKind, SizeMode};
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_dir_find() {
@@ -191,16 +1014,87 @@ mod test {
         assert_eq!(found.path, Path::new("./src/").to_path_buf());
     }
 
+    #[test]
+    fn test_name() {
+        let dir = |path: &str| make_file(path, 0);
+
+        assert_eq!(dir("./src/lib.rs").name(), "lib.rs");
+        assert_eq!(dir("/").name(), "/");
+        assert_eq!(dir("..").name(), "..");
+        assert_eq!(dir(".").name(), ".");
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+            let path = Path::new("/tmp").join(OsStr::from_bytes(b"bad\xffname"));
+            let dir = Dir::new(0, path, None, EntryKind::File);
+            assert_eq!(dir.name(), "bad\u{FFFD}name");
+        }
+    }
+
     #[test]
     fn test_filter_size() {
         let root = Path::new(".");
         let tree = make_dir_tree_parallel(root.to_path_buf());
         let size_min = 1000000;
-        let filtered = tree.filter_size(size_min);
+        let filtered = tree.filter_size(size_min, SizeMode::Apparent);
 
         for filt in filtered.unwrap().iter() {
             // println!("{}", filt.display_default())
             assert!(filt.size > size_min);
         }
     }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut tree = Dir::from_contents(
+            PathBuf::from("/data"),
+            vec![Dir::from_contents(
+                PathBuf::from("/data/logs"),
+                vec![make_file("/data/logs/a.log", 100)],
+            )],
+        );
+
+        assert!(tree.insert(make_file("/data/logs/b.log", 50)));
+        assert_eq!((tree.size, tree.file_count), (150, 2));
+        // an entry with the same path is replaced
+        assert!(tree.insert(make_file("/data/logs/b.log", 70)));
+        assert_eq!((tree.size, tree.file_count), (170, 2));
+        assert!(!tree.insert(make_file("/data/missing/c.log", 10)));
+
+        let removed = tree.remove(Path::new("/data/logs/a.log")).unwrap();
+        assert_eq!(removed.size, 100);
+        assert_eq!((tree.size, tree.file_count), (70, 1));
+        assert!(tree.remove(Path::new("/data/logs/a.log")).is_none());
+        assert!(tree.remove(Path::new("/data")).is_none());
+    }
+
+    #[test]
+    fn test_remove_hard_links() {
+        let link = |path: &str| {
+            let mut link = make_file(path, 100);
+            link.inode = Some((1, 1));
+            link
+        };
+        let dir = |path: &str, contents| Dir::from_contents(PathBuf::from(path), contents);
+        let mut tree = dir(
+            "/data",
+            vec![
+                dir("/data/a", vec![link("/data/a/file")]),
+                dir("/data/b", vec![link("/data/b/link")]),
+            ],
+        );
+        tree.count_links_once();
+        assert_eq!(tree.size, 100);
+        assert!(tree.get(Path::new("/data/b/link")).unwrap().hard_link);
+
+        // the inode stays as long as one of its links does
+        let [a, b] = ["/data/a", "/data/b"].map(|path| tree.get(Path::new(path)).unwrap());
+        assert_eq!(tree.removed_size(&[a], SizeMode::Apparent), 0);
+        assert_eq!(tree.removed_size(&[b], SizeMode::Apparent), 0);
+        assert_eq!(tree.removed_size(&[a, b], SizeMode::Apparent), 100);
+        tree.remove(Path::new("/data/a"));
+        assert_eq!(tree.size, 100);
+        assert!(!tree.get(Path::new("/data/b/link")).unwrap().hard_link);
+    }
 }
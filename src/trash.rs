@@ -0,0 +1,280 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An entry that was moved to the trash, it can be put back where it was
+///
+/// contains:
+/// - original - the path the entry was moved from
+/// - trashed - the path of the entry in the `files` directory of the trash
+/// - info - the `.trashinfo` file recording where the entry came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashedEntry {
+    pub original: PathBuf,
+    pub trashed: PathBuf,
+    pub info: PathBuf,
+}
+
+impl TrashedEntry {
+    /// Moves the entry from the trash back to where it was, fails if something else was put there
+    /// in the meantime
+    pub fn restore(&self) -> io::Result<()> {
+        if fs::symlink_metadata(&self.original).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", self.original.display()),
+            ));
+        }
+        fs::rename(&self.trashed, &self.original)?;
+        fs::remove_file(&self.info)
+    }
+}
+
+/// Moves a file or directory to the trash, following the freedesktop.org trash specification
+///
+/// Entries on the filesystem of the home directory go to the home trash (`~/.local/share/Trash`),
+/// entries on other filesystems to the trash at the top of their mount (`.Trash/$uid` or
+/// `.Trash-$uid`), so they are only renamed and never copied.
+#[cfg(unix)]
+pub fn trash(path: &Path) -> io::Result<TrashedEntry> {
+    trash_with_home(path, &home_trash()?)
+}
+
+/// Moves a file or directory to the trash, not supported on this platform
+#[cfg(not(unix))]
+pub fn trash(path: &Path) -> io::Result<TrashedEntry> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} can't be moved to the trash on this platform",
+            path.display()
+        ),
+    ))
+}
+
+/// The trash in the home directory, `$XDG_DATA_HOME/Trash`
+#[cfg(unix)]
+fn home_trash() -> io::Result<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|data_home| data_home.is_absolute());
+    let data_home = match data_home {
+        Some(data_home) => data_home,
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/share"),
+            None => {
+                let message = "the home directory is not known, HOME is not set";
+                return Err(io::Error::new(io::ErrorKind::NotFound, message));
+            }
+        },
+    };
+    Ok(data_home.join("Trash"))
+}
+
+#[cfg(unix)]
+fn trash_with_home(path: &Path, home_trash: &Path) -> io::Result<TrashedEntry> {
+    use std::io::Write;
+    use std::os::unix::fs::MetadataExt;
+    use std::time::SystemTime;
+
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} can't be moved to the trash", path.display()),
+        )
+    })?;
+    fs::symlink_metadata(path)?;
+    // the entry is renamed within the filesystem of its directory
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)?,
+        _ => std::env::current_dir()?,
+    };
+    let device = fs::metadata(&parent)?.dev();
+    let home_device = home_trash
+        .ancestors()
+        .find_map(|ancestor| fs::metadata(ancestor).ok())
+        .map(|metadata| metadata.dev());
+    let trash_dir = if home_device == Some(device) {
+        home_trash.to_path_buf()
+    } else {
+        mount_trash(&parent, device)?
+    };
+    let (files, info) = (trash_dir.join("files"), trash_dir.join("info"));
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        escape(&parent.join(name)),
+        deletion_date(SystemTime::now())
+    );
+    let mut number = 1;
+    loop {
+        let mut trash_name = name.to_os_string();
+        if number > 1 {
+            trash_name.push(format!(".{}", number));
+        }
+        number += 1;
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = info.join(info_name);
+        // creating the info file reserves the name in the trash
+        let mut info_file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(info_file) => info_file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+        let trashed = files.join(&trash_name);
+        if fs::symlink_metadata(&trashed).is_ok() {
+            fs::remove_file(&info_path)?;
+            continue;
+        }
+        let moved = info_file
+            .write_all(contents.as_bytes())
+            .and_then(|()| fs::rename(path, &trashed));
+        if let Err(err) = moved {
+            let _ = fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(TrashedEntry {
+            original: path.to_path_buf(),
+            trashed,
+            info: info_path,
+        });
+    }
+}
+
+/// The trash at the top of the mount a directory is on, `$topdir/.Trash/$uid` if the
+/// administrator set up a shared trash there and `$topdir/.Trash-$uid` otherwise
+#[cfg(unix)]
+fn mount_trash(dir: &Path, device: u64) -> io::Result<PathBuf> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let top_dir = dir
+        .ancestors()
+        .take_while(|ancestor| {
+            fs::metadata(ancestor).is_ok_and(|metadata| metadata.dev() == device)
+        })
+        .last()
+        .unwrap_or(dir);
+    // SAFETY: getuid can't fail and has no side effects
+    let uid = unsafe { libc::getuid() };
+    // the shared trash is only used if it's a real directory with the sticky bit set
+    let shared = top_dir.join(".Trash");
+    let sticky = fs::symlink_metadata(&shared)
+        .is_ok_and(|metadata| metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0);
+    if sticky {
+        let trash = shared.join(uid.to_string());
+        if create_private_dir(&trash, uid).is_ok() {
+            return Ok(trash);
+        }
+    }
+    let trash = top_dir.join(format!(".Trash-{}", uid));
+    create_private_dir(&trash, uid)?;
+    Ok(trash)
+}
+
+/// Creates a directory only the user can access, an existing one has to be a directory owned by
+/// the user
+#[cfg(unix)]
+fn create_private_dir(path: &Path, uid: u32) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match fs::DirBuilder::new().mode(0o700).create(path) {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a trash directory of the user", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Percent-encodes a path the way URLs are, as the trash specification requires
+#[cfg(unix)]
+fn escape(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut escaped = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/// Local time in the format of the trash specification, e.g. `2004-08-31T22:32:08`
+#[cfg(unix)]
+fn deletion_date(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs()) as libc::time_t;
+    // SAFETY: localtime_r only writes to the given struct, which is plain data
+    let tm = unsafe {
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&secs, &mut tm);
+        tm
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use crate::trash::trash_with_home;
+    use std::fs;
+
+    #[test]
+    fn test_trash_and_restore() {
+        let root = tempfile::tempdir().unwrap();
+        let home_trash = root.path().join("Trash");
+        let data = root.path().join("data");
+        fs::create_dir_all(data.join("nested")).unwrap();
+        fs::write(data.join("nested/file"), "nested").unwrap();
+        let file = root.path().join("a b.txt");
+        fs::write(&file, "first").unwrap();
+
+        let first = trash_with_home(&file, &home_trash).unwrap();
+        assert!(!file.exists());
+        assert_eq!(first.trashed, home_trash.join("files/a b.txt"));
+        let info = fs::read_to_string(&first.info).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"));
+        assert!(info.contains("/a%20b.txt\nDeletionDate="));
+
+        // entries with the same name get different names in the trash
+        fs::write(&file, "second").unwrap();
+        let second = trash_with_home(&file, &home_trash).unwrap();
+        assert_ne!(second.trashed, first.trashed);
+        assert_eq!(fs::read_to_string(&second.trashed).unwrap(), "second");
+
+        // entries are not restored over new ones
+        fs::write(&file, "third").unwrap();
+        assert!(first.restore().is_err());
+        fs::remove_file(&file).unwrap();
+        first.restore().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "first");
+        assert!(!first.info.exists());
+
+        let dir = trash_with_home(&data, &home_trash).unwrap();
+        assert!(!data.exists());
+        assert!(dir.trashed.join("nested/file").exists());
+    }
+}
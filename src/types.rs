@@ -0,0 +1,154 @@
+use crate::structs::{sort_usage, Dir, SizeMode, Usage};
+
+/// Coarse category of a file, guessed from its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileCategory {
+    Video,
+    Images,
+    Audio,
+    Archives,
+    Documents,
+    Source,
+    Binaries,
+    Other,
+}
+
+impl FileCategory {
+    /// Guesses the category from a lowercase file extension
+    pub fn from_extension(extension: &str) -> Self {
+        match extension {
+            // .ts is far more often TypeScript than an MPEG transport stream, those are only
+            // recognized by their longer extensions
+            "mp4" | "mkv" | "avi" | "mov" | "wmv" | "flv" | "webm" | "m4v" | "mpg" | "mpeg"
+            | "m2ts" | "mts" | "vob" => FileCategory::Video,
+            "jpg" | "jpeg" | "png" | "gif" | "bmp" | "tif" | "tiff" | "webp" | "svg" | "ico"
+            | "heic" | "raw" | "cr2" | "nef" | "psd" | "xcf" => FileCategory::Images,
+            "mp3" | "flac" | "wav" | "ogg" | "opus" | "m4a" | "aac" | "wma" => FileCategory::Audio,
+            "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "iso" | "img"
+            | "dmg" | "deb" | "rpm" | "jar" | "whl" | "crate" => FileCategory::Archives,
+            "pdf" | "doc" | "docx" | "odt" | "xls" | "xlsx" | "ods" | "ppt" | "pptx" | "odp"
+            | "txt" | "md" | "rtf" | "epub" | "csv" => FileCategory::Documents,
+            "rs" | "c" | "h" | "cpp" | "hpp" | "cc" | "py" | "js" | "jsx" | "ts" | "tsx"
+            | "java" | "kt" | "go" | "rb" | "php" | "cs" | "swift" | "sh" | "html" | "css"
+            | "scss" | "json" | "toml" | "yaml" | "yml" | "xml" | "sql" | "lua" | "hs" => {
+                FileCategory::Source
+            }
+            "exe" | "dll" | "so" | "dylib" | "a" | "o" | "obj" | "lib" | "rlib" | "rmeta"
+            | "bin" | "class" | "pyc" | "wasm" | "msi" => FileCategory::Binaries,
+            _ => FileCategory::Other,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            FileCategory::Video => "video",
+            FileCategory::Images => "images",
+            FileCategory::Audio => "audio",
+            FileCategory::Archives => "archives",
+            FileCategory::Documents => "documents",
+            FileCategory::Source => "source",
+            FileCategory::Binaries => "binaries",
+            FileCategory::Other => "other",
+        }
+    }
+}
+
+impl Dir {
+    /// Lowercase extension of the entry, None for directories and files without one
+    pub fn extension(&self) -> Option<String> {
+        if self.is_dir() {
+            return None;
+        }
+        let extension = self.path.extension()?;
+        Some(extension.to_string_lossy().to_lowercase())
+    }
+
+    /// Category of the entry guessed from its extension
+    pub fn category(&self) -> FileCategory {
+        match self.extension() {
+            Some(extension) => FileCategory::from_extension(&extension),
+            None => FileCategory::Other,
+        }
+    }
+
+    /// Sums up the usage of the files in the tree by their extension, sorted from the biggest to
+    /// the smallest extension in the given size mode
+    ///
+    /// Files without an extension (and collapsed directories, whose files are unknown) are
+    /// grouped under None
+    pub fn usage_by_extension(&self, mode: SizeMode) -> Vec<(Option<String>, Usage)> {
+        sort_usage(self.usage_by(Dir::extension), mode)
+    }
+
+    /// Sums up the usage of the files in the tree by their category, sorted from the biggest to
+    /// the smallest category in the given size mode
+    pub fn usage_by_category(&self, mode: SizeMode) -> Vec<(FileCategory, Usage)> {
+        sort_usage(self.usage_by(Dir::category), mode)
+    }
+}
+
+/// Name shown for an extension group, files without an extension are grouped under `(none)`
+pub fn extension_label(extension: Option<String>) -> String {
+    match extension {
+        Some(extension) => format!(".{}", extension),
+        None => "(none)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::structs::{make_file as file, Dir, SizeMode};
+    use crate::types::FileCategory;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_usage_by_type() {
+        let mut collapsed = Dir::from_contents(
+            PathBuf::from("/data/target.d"),
+            vec![file("/data/target.d/x.o", 40)],
+        );
+        collapsed.collapse();
+        let tree = Dir::from_contents(
+            PathBuf::from("/data"),
+            vec![
+                file("/data/movie.MKV", 300),
+                file("/data/clip.mp4", 100),
+                file("/data/photo.jpg", 60),
+                file("/data/README", 10),
+                collapsed,
+            ],
+        );
+
+        let extensions = tree.usage_by_extension(SizeMode::Apparent);
+        let sizes: Vec<(Option<&str>, u64, u64)> = extensions
+            .iter()
+            .map(|(ext, usage)| (ext.as_deref(), usage.size, usage.file_count))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (Some("mkv"), 300, 1),
+                (Some("mp4"), 100, 1),
+                (Some("jpg"), 60, 1),
+                (None, 50, 2),
+            ]
+        );
+
+        let categories = tree.usage_by_category(SizeMode::Apparent);
+        assert_eq!(categories[0].0, FileCategory::Video);
+        assert_eq!(categories[0].1.size, 400);
+        assert_eq!(categories[0].1.file_count, 2);
+        assert_eq!(categories.len(), 3);
+        let total: u64 = categories.iter().map(|(_, usage)| usage.size).sum();
+        assert_eq!(total, tree.size);
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(FileCategory::from_extension("ts"), FileCategory::Source);
+        assert_eq!(FileCategory::from_extension("tsx"), FileCategory::Source);
+        assert_eq!(FileCategory::from_extension("m2ts"), FileCategory::Video);
+        assert_eq!(FileCategory::from_extension("mts"), FileCategory::Video);
+        assert_eq!(FileCategory::from_extension("unknown"), FileCategory::Other);
+    }
+}
@@ -0,0 +1,416 @@
+use crate::scanning::{scan_entry, ScanOptions};
+use crate::structs::{Dir, SharedDir};
+use log::{debug, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long changes are collected before the tree is updated, so a burst of changes to the same
+/// entries only scans them once
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// State of a watcher, shared with the menu showing the watched tree
+///
+/// Clones share the same state, so the watcher can be stopped and checked from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct WatchState(Arc<WatchShared>);
+
+#[derive(Debug, Default)]
+struct WatchShared {
+    updates: AtomicU64,
+    stopped: AtomicBool,
+    error: Mutex<Option<String>>,
+    rescanned: Mutex<Vec<PathBuf>>,
+    subscribers: Mutex<Vec<mpsc::Sender<WatchEvent>>>,
+}
+
+/// What the watcher did, sent to the receivers of [`WatchState::subscribe`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// the directories of the tree, or of the entries scanned again outside of the watcher, are
+    /// watched from now on
+    Watching,
+    /// the entries were scanned again after they changed and the tree was updated
+    Updated(Vec<PathBuf>),
+}
+
+impl WatchState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of times the tree was updated, changes whenever the tree changed
+    pub fn updates(&self) -> u64 {
+        self.0.updates.load(Ordering::Relaxed)
+    }
+
+    /// Why not all changes are picked up, if they aren't
+    pub fn error(&self) -> Option<String> {
+        self.0.error.lock().unwrap().clone()
+    }
+
+    /// Stops the watcher using this state
+    pub fn stop(&self) {
+        self.0.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Tells the watcher that an entry of the tree was scanned again outside of it, so the
+    /// directories found in it are watched as well
+    pub fn rescanned(&self, path: PathBuf) {
+        self.0.rescanned.lock().unwrap().push(path);
+    }
+
+    /// Receives everything the watcher does from now on
+    pub fn subscribe(&self) -> mpsc::Receiver<WatchEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    fn send(&self, event: WatchEvent) {
+        let mut subscribers = self.0.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn updated(&self, scanned: Vec<PathBuf>) {
+        self.0.updates.fetch_add(1, Ordering::Relaxed);
+        self.send(WatchEvent::Updated(scanned));
+    }
+
+    fn fail(&self, error: String) {
+        warn!("{}", error);
+        self.0.error.lock().unwrap().get_or_insert(error);
+    }
+}
+
+/// Watches a scanned tree for changes and updates it in place, so it can be shown while the
+/// files in it are created, grown and deleted
+///
+/// Every directory of the tree is watched on its own, collapsed directories are watched with
+/// everything below them and scanned again as a whole when anything in them changes. Changed
+/// entries are scanned again and replace the old ones, summing up the sizes of all of their
+/// parents again. Changes made while the tree was scanned are not picked up. Blocks until the
+/// watcher is stopped, so it is usually run on its own thread.
+///
+/// Args:
+/// - tree - the scanned tree, its path is the directory that is watched
+/// - options - the options the tree was scanned with
+/// - state - the state shared with the menu, the watcher is stopped with it
+pub fn watch_tree(tree: SharedDir, options: &ScanOptions, state: WatchState) {
+    let options = ScanOptions {
+        progress: None,
+        cancel: None,
+        cache: None,
+        ..options.clone()
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(err) => return state.fail(format!("could not watch for changes: {}", err)),
+    };
+    let (root, dirs) = {
+        let tree = tree.lock().unwrap();
+        let mut dirs = vec![];
+        watched_dirs(&tree, &mut dirs);
+        (tree.path.clone(), dirs)
+    };
+    watch_dirs(&mut watcher, dirs, &state);
+    state.send(WatchEvent::Watching);
+
+    while !state.is_stopped() {
+        let rescanned = std::mem::take(&mut *state.0.rescanned.lock().unwrap());
+        if !rescanned.is_empty() {
+            for path in rescanned {
+                let mut dirs = vec![];
+                if let Some(dir) = tree.lock().unwrap().get(&path) {
+                    watched_dirs(dir, &mut dirs);
+                }
+                watch_dirs(&mut watcher, dirs, &state);
+            }
+            state.send(WatchEvent::Watching);
+        }
+
+        // waiting for the first change, then collecting the ones that follow it
+        let event = match receiver.recv_timeout(BATCH_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let mut changed = BTreeSet::new();
+        let mut rescan = collect_event(event, &mut changed, &state);
+        let batch_end = Instant::now() + BATCH_INTERVAL;
+        while let Ok(event) =
+            receiver.recv_timeout(batch_end.saturating_duration_since(Instant::now()))
+        {
+            rescan |= collect_event(event, &mut changed, &state);
+        }
+        if rescan {
+            // events were lost, only scanning everything again catches up with them
+            changed = BTreeSet::from([root.clone()]);
+        }
+        // nothing but reads, the tree didn't change
+        if changed.is_empty() {
+            continue;
+        }
+
+        // the paths are sorted, so entries below a scanned entry directly follow it
+        let mut scanned: Vec<PathBuf> = vec![];
+        for path in changed {
+            if scanned
+                .last()
+                .is_some_and(|scanned| path.starts_with(scanned))
+            {
+                continue;
+            }
+            if let Some((path, dirs)) = update_entry(&tree, &root, &path, &options) {
+                watch_dirs(&mut watcher, dirs, &state);
+                scanned.push(path);
+            }
+        }
+        state.updated(scanned);
+    }
+}
+
+/// Adds the paths of an event to the changed paths
+///
+/// Returns true if events were lost and the whole tree has to be scanned again
+fn collect_event(
+    event: notify::Result<Event>,
+    changed: &mut BTreeSet<PathBuf>,
+    state: &WatchState,
+) -> bool {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            if let notify::ErrorKind::MaxFilesWatch = err.kind {
+                state.fail(format!("not all directories are watched: {}", err));
+            } else {
+                warn!("Error occured while watching for changes: {}", err);
+            }
+            return false;
+        }
+    };
+    if event.need_rescan() {
+        return true;
+    }
+    // reading a file doesn't change its size
+    if !matches!(event.kind, EventKind::Access(_)) {
+        debug!("{:?} changed: {:?}", event.kind, event.paths);
+        changed.extend(event.paths);
+    }
+    false
+}
+
+/// Scans a changed entry again and replaces it in the tree, removes it if it doesn't exist anymore
+///
+/// Entries within collapsed directories scan the whole collapsed directory again, entries whose
+/// parent is not in the tree (excluded or not scanned) are skipped. Returns the path that was
+/// scanned and the directories within it that have to be watched.
+fn update_entry(
+    tree: &SharedDir,
+    root: &Path,
+    path: &Path,
+    options: &ScanOptions,
+) -> Option<(PathBuf, Vec<(PathBuf, RecursiveMode)>)> {
+    let (target, ignored) = {
+        let tree = tree.lock().unwrap();
+        let ancestor = if path == root {
+            &*tree
+        } else {
+            path.ancestors()
+                .skip(1)
+                .find_map(|ancestor| tree.get(ancestor))?
+        };
+        if ancestor.collapsed {
+            (ancestor.path.clone(), ancestor.ignored)
+        } else if path == root {
+            (path.to_path_buf(), false)
+        } else if Some(ancestor.path.as_path()) == path.parent() {
+            // everything below an ignored directory is ignored
+            (path.to_path_buf(), ancestor.ignored)
+        } else {
+            return None;
+        }
+    };
+
+    let entry = scan_entry(root, target.clone(), options);
+    let mut dirs = vec![];
+    let mut tree = tree.lock().unwrap();
+    match entry {
+        Some(mut entry) => {
+            watched_dirs(&entry, &mut dirs);
+            if ignored {
+                entry.mark_ignored();
+            }
+            if target == root {
+                tree.splice(entry);
+            } else {
+                tree.insert(entry);
+            }
+        }
+        None => {
+            tree.remove(&target);
+        }
+    }
+    Some((target, dirs))
+}
+
+/// Collects the directories of a tree that are watched, collapsed directories are watched with
+/// everything below them
+fn watched_dirs(dir: &Dir, dirs: &mut Vec<(PathBuf, RecursiveMode)>) {
+    match &dir.contents {
+        Some(contents) => {
+            dirs.push((dir.path.clone(), RecursiveMode::NonRecursive));
+            for sub_dir in contents {
+                watched_dirs(sub_dir, dirs);
+            }
+        }
+        None if dir.collapsed => dirs.push((dir.path.clone(), RecursiveMode::Recursive)),
+        None => {}
+    }
+}
+
+/// Starts watching the directories, stops once the limit of watched directories is reached
+fn watch_dirs(
+    watcher: &mut RecommendedWatcher,
+    dirs: Vec<(PathBuf, RecursiveMode)>,
+    state: &WatchState,
+) {
+    for (path, mode) in dirs {
+        match watcher.watch(&path, mode) {
+            Ok(()) => {}
+            Err(err) if matches!(err.kind, notify::ErrorKind::MaxFilesWatch) => {
+                return state.fail(format!("not all directories are watched: {}", err));
+            }
+            Err(err) => warn!("Error occured when watching {}: {}", path.display(), err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scanning::{dirs_size, make_dir_tree_with, make_dir_tree_within, ScanOptions};
+    use crate::structs::Dir;
+    use crate::watch::{watch_tree, WatchEvent, WatchState};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::mpsc::Receiver;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    /// How long an event of the watcher is waited for before failing
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Receives the updates of the watcher until the tree matches the condition, returns the
+    /// entries that were scanned again
+    fn wait_for(
+        events: &Receiver<WatchEvent>,
+        tree: &Mutex<Dir>,
+        condition: impl Fn(&Dir) -> bool,
+    ) -> Vec<PathBuf> {
+        let mut scanned = vec![];
+        while !condition(&tree.lock().unwrap()) {
+            match events.recv_timeout(TIMEOUT) {
+                Ok(WatchEvent::Updated(paths)) => scanned.extend(paths),
+                event => panic!("tree was not updated, got {:?}", event),
+            }
+        }
+        scanned
+    }
+
+    #[test]
+    fn test_watch_tree() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("logs")).unwrap();
+        fs::write(root.path().join("logs/a.log"), [0; 100]).unwrap();
+        let options = ScanOptions::default();
+        let tree = Arc::new(Mutex::new(make_dir_tree_with(
+            root.path().to_path_buf(),
+            &options,
+        )));
+        let state = WatchState::new();
+        let events = state.subscribe();
+        let watcher = {
+            let (tree, state) = (tree.clone(), state.clone());
+            thread::spawn(move || watch_tree(tree, &options, state))
+        };
+        // the watches are added on the thread of the watcher
+        assert_eq!(events.recv_timeout(TIMEOUT), Ok(WatchEvent::Watching));
+
+        // the sizes of the files, the directories themselves are counted as well
+        let files_size = |tree: &Dir| tree.size.checked_sub(dirs_size(root.path()));
+
+        fs::write(root.path().join("logs/b.log"), [0; 50]).unwrap();
+        let scanned = wait_for(&events, &tree, |tree| {
+            files_size(tree) == Some(150) && tree.file_count == 2
+        });
+        assert!(scanned.contains(&root.path().join("logs/b.log")));
+
+        fs::create_dir_all(root.path().join("new/deeper")).unwrap();
+        fs::write(root.path().join("new/deeper/c.log"), [0; 30]).unwrap();
+        let scanned = wait_for(&events, &tree, |tree| files_size(tree) == Some(180));
+        assert!(scanned.contains(&root.path().join("new")));
+        // new directories are watched as well
+        fs::write(root.path().join("new/deeper/d.log"), [0; 20]).unwrap();
+        let scanned = wait_for(&events, &tree, |tree| {
+            files_size(tree) == Some(200) && tree.file_count == 4
+        });
+        assert!(scanned.contains(&root.path().join("new/deeper/d.log")));
+
+        fs::remove_file(root.path().join("logs/a.log")).unwrap();
+        fs::remove_dir_all(root.path().join("new")).unwrap();
+        let scanned = wait_for(&events, &tree, |tree| {
+            files_size(tree) == Some(50) && tree.file_count == 1
+        });
+        assert!(scanned.contains(&root.path().join("logs/a.log")));
+        assert!(state.updates() > 0);
+        assert!(state.error().is_none());
+
+        state.stop();
+        watcher.join().unwrap();
+    }
+
+    #[test]
+    fn test_watch_rescanned() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("data")).unwrap();
+        let options = ScanOptions::default();
+        let tree = Arc::new(Mutex::new(make_dir_tree_with(
+            root.path().to_path_buf(),
+            &options,
+        )));
+        // created before the watcher started, so it only finds out about it from the rescan
+        fs::create_dir(root.path().join("data/new")).unwrap();
+        let state = WatchState::new();
+        let events = state.subscribe();
+        let watcher = {
+            let (tree, state, options) = (tree.clone(), state.clone(), options.clone());
+            thread::spawn(move || watch_tree(tree, &options, state))
+        };
+        assert_eq!(events.recv_timeout(TIMEOUT), Ok(WatchEvent::Watching));
+
+        // the way the menu scans the listed directory again
+        let data = root.path().join("data");
+        let rescanned = make_dir_tree_within(root.path(), data.clone(), &options);
+        tree.lock().unwrap().splice(rescanned);
+        state.rescanned(data);
+        assert_eq!(events.recv_timeout(TIMEOUT), Ok(WatchEvent::Watching));
+
+        fs::write(root.path().join("data/new/file"), [0; 40]).unwrap();
+        let scanned = wait_for(&events, &tree, |tree| tree.file_count == 1);
+        assert!(scanned.contains(&root.path().join("data/new/file")));
+        assert_eq!(tree.lock().unwrap().size, 40 + dirs_size(root.path()));
+
+        state.stop();
+        watcher.join().unwrap();
+    }
+}